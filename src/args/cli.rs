@@ -1,40 +1,527 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{ArgGroup, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+
+use crate::filter::parse_size;
+use crate::state::parse_id;
+use crate::time_utils::parse_time;
+
+/// Debounce of source folders if not set, milliseconds.
+pub const DEFAULT_FS_TIMEOUT: u64 = 300;
 
 /// Command line arguments.
+///
+/// Without a subcommand sources are watched.
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct CommandLineArgs {
+    #[command(subcommand)]
+    pub command: Option<CommandArgs>,
+
+    #[command(flatten)]
+    pub watch: WatchCliArgs,
+
+    #[clap(
+        long = "naming",
+        help = "Format of version ids in backup file names",
+        default_value_t,
+        value_enum,
+        global = true
+    )]
+    pub naming: NamingScheme,
+
+    #[clap(
+        long = "dry-run",
+        help = "Log files which would be written, renamed or deleted without touching them",
+        global = true
+    )]
+    pub dry_run: bool,
+
+    #[clap(
+        long = "log-level",
+        help = "Verbosity level",
+        default_value_t,
+        value_enum,
+        global = true
+    )]
+    pub log_level: Verbosity,
+}
+
+/// Arguments of a normal run, shared with `doctor` command.
+#[derive(Debug, clap::Args)]
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "Each one is a command line flag"
+)]
+pub struct WatchCliArgs {
     #[cfg(feature = "non-existing-option")]
     #[clap(long = "force-register", help = "Register nonexistent files")]
     pub register_nonexistent: bool,
 
+    #[clap(
+        long = "once",
+        help = "Back up changed sources once and exit without watching"
+    )]
+    pub once: bool,
+
+    #[clap(
+        long = "control",
+        help = "Accept `ctl` commands on `.little-becky.sock` in the output folder"
+    )]
+    pub control: bool,
+
+    #[clap(
+        long = "control-socket",
+        value_name = "PATH",
+        help = "Accept `ctl` commands on the socket"
+    )]
+    pub control_socket: Option<PathBuf>,
+
     #[clap(
         long = "fs-timeout",
         help = "Timeout for FS notify debouncer (ms)",
-        default_value_t = 300
+        default_value_t = DEFAULT_FS_TIMEOUT
     )]
     pub fs_timeout: u64,
 
+    #[clap(
+        long = "watch-mode",
+        help = "Filesystem notification backend",
+        default_value_t,
+        value_enum
+    )]
+    pub watch_mode: WatchMode,
+
+    #[clap(
+        long = "poll-interval",
+        help = "Poll interval for polling watch mode (ms)",
+        default_value_t = 2000
+    )]
+    pub poll_interval: u64,
+
+    #[clap(
+        long = "poll-compare-contents",
+        help = "Compare file contents while polling, not only modification time"
+    )]
+    pub poll_compare_contents: bool,
+
     #[clap(
         short = 'o',
         long = "output",
-        help = "Output folder root to backup files"
+        help = "Output folder root to backup files, required unless configured, may be repeated"
     )]
-    pub destination: PathBuf,
+    pub destination: Vec<PathBuf>,
 
     #[clap(
-        long = "log-level",
-        help = "Verbosity level",
+        long = "config",
+        value_name = "PATH",
+        help = "Configuration file [default: little-becky/config.toml in the user config folder]"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[clap(
+        long = "profile",
+        value_name = "NAME",
+        help = "Apply settings and sources of the configuration profile"
+    )]
+    pub profile: Option<String>,
+
+    #[clap(
+        long = "preset",
+        value_name = "NAME|APPID",
+        help = "Watch saves of a known game, see `presets search`"
+    )]
+    pub presets: Vec<String>,
+
+    #[clap(
+        long = "print-config",
+        help = "Print effective settings merged from configuration and flags, then exit without validating them"
+    )]
+    pub print_config: bool,
+
+    #[clap(
+        long = "include",
+        value_name = "GLOB",
+        help = "Back up only matching file names in folder sources, only in the preceding source if given after one"
+    )]
+    pub include: Vec<String>,
+
+    #[clap(
+        long = "exclude",
+        value_name = "GLOB",
+        help = "Skip matching file names in folder sources, only in the preceding source if given after one"
+    )]
+    pub exclude: Vec<String>,
+
+    #[clap(
+        long = "no-default-excludes",
+        help = "Do not skip editor, temporary and lock files in folder sources"
+    )]
+    pub no_default_excludes: bool,
+
+    #[clap(
+        long = "max-file-size",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Skip larger files in folder sources (bytes, K, M, G or T suffix)"
+    )]
+    pub max_file_size: Option<u64>,
+
+    #[clap(
+        long = "rescan-interval",
+        value_name = "SECONDS",
+        help = "Periodically back up sources changed without a notification"
+    )]
+    pub rescan_interval: Option<u64>,
+
+    #[clap(
+        long = "symlinks",
+        help = "How symbolic links in sources are handled",
         default_value_t,
         value_enum
     )]
-    pub log_level: Verbosity,
+    pub symlinks: SymlinkPolicy,
+
+    #[clap(
+        long = "track",
+        help = "Follow a source by its path or by file identity when renamed",
+        default_value_t,
+        value_enum
+    )]
+    pub track: TrackMode,
+
+    #[clap(
+        long = "tombstones",
+        help = "Write an empty `<name>_<id>.<ext>.deleted` file when a source is deleted"
+    )]
+    pub tombstones: bool,
 
-    #[clap(num_args = 1.., help = "Source file(s) to monitor")]
+    #[clap(
+        num_args = 1..,
+        value_name = "[NAME=]SOURCE[:DEST]",
+        help = "Source file(s) to monitor, NAME replaces the file or folder name in backups, DEST replaces --output"
+    )]
     pub sources: Vec<PathBuf>,
 }
 
+/// Commands working with existing backups.
+#[derive(Debug, Subcommand)]
+pub enum CommandArgs {
+    /// Check sources, destinations and watch limits of a normal run.
+    Doctor(WatchCliArgs),
+    /// Show version history of sources without watching them.
+    List(ListArgs),
+    /// Copy a backed up version back to its source.
+    Restore(RestoreArgs),
+    /// Show difference between two backed up versions.
+    Diff(DiffArgs),
+    /// Renumber versions densely by time and convert naming.
+    Reindex(ReindexArgs),
+    /// Import backups made by other tools or by hand into version history.
+    Import(ImportArgs),
+    /// Control a running watcher started with `--control`.
+    Ctl(CtlArgs),
+    /// Browse known game save locations for `--preset`.
+    Presets(PresetsArgs),
+}
+
+/// Arguments of `list` command.
+#[derive(Debug, clap::Args)]
+pub struct ListArgs {
+    #[clap(
+        short = 'o',
+        long = "output",
+        help = "Output folder root with backup files"
+    )]
+    pub destination: PathBuf,
+
+    #[clap(long = "json", help = "Print versions as JSON")]
+    pub json: bool,
+
+    #[clap(
+        long = "since",
+        value_name = "TIME",
+        value_parser = parse_time,
+        help = "Show versions modified at or after the time"
+    )]
+    pub since: Option<u128>,
+
+    #[clap(
+        long = "until",
+        value_name = "TIME",
+        value_parser = parse_time,
+        help = "Show versions modified at or before the time"
+    )]
+    pub until: Option<u128>,
+
+    #[clap(
+        value_name = "[NAME=]SOURCE[:DEST]",
+        help = "Source file(s) or folder(s) as they are watched, all backups if omitted"
+    )]
+    pub sources: Vec<PathBuf>,
+}
+
+/// Arguments of `restore` command.
+#[derive(Debug, clap::Args)]
+#[command(group(ArgGroup::new("version").required(true)))]
+pub struct RestoreArgs {
+    #[clap(
+        short = 'o',
+        long = "output",
+        help = "Output folder root with backup files"
+    )]
+    pub destination: PathBuf,
+
+    #[clap(
+        long = "id",
+        value_name = "HEX",
+        value_parser = parse_id,
+        group = "version",
+        help = "Restore the version with the id (file sources only)"
+    )]
+    pub id: Option<u64>,
+
+    #[clap(
+        long = "latest",
+        group = "version",
+        help = "Restore the latest version"
+    )]
+    pub latest: bool,
+
+    #[clap(
+        long = "at",
+        value_name = "TIME",
+        value_parser = parse_time,
+        group = "version",
+        help = "Restore the version current at the time"
+    )]
+    pub at: Option<u128>,
+
+    #[clap(
+        long = "previous",
+        value_name = "N",
+        group = "version",
+        help = "Restore the N-th version before the latest"
+    )]
+    pub previous: Option<usize>,
+
+    #[clap(
+        value_name = "[NAME=]SOURCE[:DEST]",
+        help = "Source file or folder to restore as it's watched"
+    )]
+    pub source: PathBuf,
+}
+
+/// Arguments of `diff` command.
+#[derive(Debug, clap::Args)]
+pub struct DiffArgs {
+    #[clap(
+        short = 'o',
+        long = "output",
+        help = "Output folder root with backup files"
+    )]
+    pub destination: PathBuf,
+
+    #[clap(
+        value_name = "[NAME=]SOURCE[:DEST]",
+        help = "Source file as it's watched"
+    )]
+    pub source: PathBuf,
+
+    #[clap(value_name = "ID_A", value_parser = parse_id, help = "Old version id (hex)")]
+    pub id_a: u64,
+
+    #[clap(value_name = "ID_B", value_parser = parse_id, help = "New version id (hex)")]
+    pub id_b: u64,
+}
+
+/// Arguments of `reindex` command.
+#[derive(Debug, clap::Args)]
+pub struct ReindexArgs {
+    #[clap(
+        short = 'o',
+        long = "output",
+        help = "Output folder root with backup files"
+    )]
+    pub destination: PathBuf,
+
+    #[clap(
+        long = "to",
+        value_enum,
+        help = "Convert version ids to the naming, each folder keeps its naming if not set"
+    )]
+    pub to: Option<NamingScheme>,
+}
+
+/// Arguments of `import` command.
+#[derive(Debug, clap::Args)]
+pub struct ImportArgs {
+    #[clap(
+        short = 'o',
+        long = "output",
+        help = "Output folder root with backup files"
+    )]
+    pub destination: PathBuf,
+
+    #[clap(
+        long = "pattern",
+        value_name = "PRESET|REGEX",
+        help = "`numbered`, `bak`, `dated` or a regex matching file names, \
+                named groups `date` and `n` order files"
+    )]
+    pub pattern: String,
+
+    #[clap(long = "move", help = "Move files instead of copying them")]
+    pub move_files: bool,
+
+    #[clap(value_name = "SRC_DIR", help = "Folder with files to import")]
+    pub folder: PathBuf,
+
+    #[clap(
+        value_name = "[NAME=]SOURCE[:DEST]",
+        help = "Source file the imported files are versions of, as it's watched"
+    )]
+    pub source: PathBuf,
+}
+
+/// Arguments of `ctl` command.
+#[derive(Debug, clap::Args)]
+#[command(group(ArgGroup::new("socket_path").required(true)))]
+pub struct CtlArgs {
+    #[clap(
+        short = 'o',
+        long = "output",
+        group = "socket_path",
+        help = "Output folder root of the watcher"
+    )]
+    pub destination: Option<PathBuf>,
+
+    #[clap(
+        long = "socket",
+        value_name = "PATH",
+        group = "socket_path",
+        help = "Control socket of the watcher"
+    )]
+    pub socket: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub action: CtlAction,
+}
+
+/// Arguments of `presets` command.
+#[derive(Debug, clap::Args)]
+pub struct PresetsArgs {
+    #[command(subcommand)]
+    pub action: PresetsAction,
+}
+
+/// Actions of `presets` command.
+#[derive(Debug, Subcommand)]
+pub enum PresetsAction {
+    /// Show presets with the query in their name, title or Steam application id.
+    Search {
+        #[clap(help = "Text to search, all presets if omitted")]
+        query: Option<String>,
+    },
+}
+
+/// Requests to a running watcher.
+#[derive(Debug, Subcommand)]
+pub enum CtlAction {
+    /// Show registered files with their last backup.
+    Status,
+    /// Start watching a source.
+    Add {
+        #[clap(help = "Source file or folder")]
+        path: PathBuf,
+    },
+    /// Stop watching a source, its backups are kept.
+    Remove {
+        #[clap(help = "Source file or folder")]
+        path: PathBuf,
+    },
+    /// Pause backups of a source.
+    Pause {
+        #[clap(help = "Source file or folder")]
+        path: PathBuf,
+    },
+    /// Resume backups of a source and back up its changes.
+    Resume {
+        #[clap(help = "Source file or folder")]
+        path: PathBuf,
+    },
+    /// Back up a source now even if it's unchanged.
+    Backup {
+        #[clap(help = "Source file or folder")]
+        path: PathBuf,
+    },
+    /// Stop the watcher.
+    Shutdown,
+}
+
+/// Format of version ids in backup file names.
+#[derive(clap::ValueEnum, Clone, Debug)] // required for clap::ValueEnum
+#[derive(Copy, PartialEq, Eq)] // to compare and pass around
+#[derive(Default)] // for set default in easier way
+#[derive(Serialize, Deserialize)] // for configuration files
+#[serde(rename_all = "kebab-case")]
+pub enum NamingScheme {
+    /// Hexadecimal, e.g. `save_1f.sav`.
+    #[default]
+    Hex,
+    /// Hexadecimal padded to 8 digits, e.g. `save_0000001f.sav`.
+    PaddedHex,
+    /// Decimal, e.g. `save_31.sav`.
+    Decimal,
+}
+
+/// Filesystem notification backend.
+#[derive(clap::ValueEnum, Clone, Debug)] // required for clap::ValueEnum
+#[derive(Copy, PartialEq, Eq)] // to compare and pass around
+#[derive(Default)] // for set default in easier way
+#[derive(Serialize, Deserialize)] // for configuration files
+#[serde(rename_all = "kebab-case")]
+pub enum WatchMode {
+    /// Native notifications (`inotify`, `FSEvents`, `ReadDirectoryChangesW`).
+    Native,
+    /// Poll folders, e.g. for network and FUSE filesystems.
+    Poll,
+    /// Native notifications, polling if native watches are exhausted.
+    #[default]
+    Auto,
+}
+
+/// How symbolic links are handled for sources and files in folder sources.
+#[derive(clap::ValueEnum, Clone, Debug)] // required for clap::ValueEnum
+#[derive(Copy, PartialEq, Eq)] // to compare and pass around
+#[derive(Default)] // for set default in easier way
+#[derive(Serialize, Deserialize)] // for configuration files
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    /// Resolve links and watch the real location.
+    #[default]
+    Follow,
+    /// Ignore links.
+    Skip,
+    /// Back up links as links.
+    CopyLink,
+}
+
+/// How a source is followed when it is renamed inside a watched folder.
+#[derive(clap::ValueEnum, Clone, Debug)] // required for clap::ValueEnum
+#[derive(Copy, PartialEq, Eq)] // to compare and pass around
+#[derive(Default)] // for set default in easier way
+#[derive(Serialize, Deserialize)] // for configuration files
+#[serde(rename_all = "kebab-case")]
+pub enum TrackMode {
+    /// History belongs to the path, a renamed file starts a new history.
+    #[default]
+    Path,
+    /// History follows the file (device and inode) to its new path.
+    File,
+}
+
 /// Wrapper to `log::LoggerLevel` to implement `ValueEnum` to list options for a user.
 #[derive(clap::ValueEnum, Clone)] // required for clap::ValueEnum
 #[derive(Debug)] // for clap parser
@@ -0,0 +1,245 @@
+use alloc::collections::BTreeMap;
+use std::env;
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf, absolute};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::expand::expand_path;
+use super::{CliError, NamingScheme, SymlinkPolicy, TrackMode, WatchMode};
+use crate::utils::{home, xdg_folder};
+
+/// Configuration file name in the configuration folder.
+const CONFIG_FILE: &str = "little-becky/config.toml";
+
+/// Configuration file: settings and sources, optionally overridden by a profile.
+///
+/// Relative paths are resolved against the folder of the file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ConfigFile {
+    #[serde(flatten)]
+    pub settings: Settings,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceEntry>,
+
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Named set of settings and sources.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Profile {
+    #[serde(flatten)]
+    pub settings: Settings,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceEntry>,
+}
+
+/// Source with its own settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SourceEntry {
+    pub path: PathBuf,
+
+    /// Alias replacing the file or folder name in backups.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(flatten)]
+    pub settings: SourceSettings,
+}
+
+/// Settings of the watcher, not set values are inherited.
+///
+/// Names are the same as command line flags.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Settings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watch_mode: Option<WatchMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poll_interval: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poll_compare_contents: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rescan_interval: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track: Option<TrackMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tombstones: Option<bool>,
+
+    #[serde(flatten)]
+    pub source: SourceSettings,
+}
+
+/// Settings a source may override.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SourceSettings {
+    /// Output folder roots, a single path or a list.
+    #[serde(
+        default,
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub output: Option<Vec<PathBuf>>,
+    /// Debounce of the source folder in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fs_timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_default_excludes: Option<bool>,
+    /// Size with optional `K`, `M`, `G` or `T` suffix.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_file_size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symlinks: Option<SymlinkPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub naming: Option<NamingScheme>,
+}
+
+impl Settings {
+    /// Override values set in `other`.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            watch_mode: other.watch_mode.or(self.watch_mode),
+            poll_interval: other.poll_interval.or(self.poll_interval),
+            poll_compare_contents: other
+                .poll_compare_contents
+                .or(self.poll_compare_contents),
+            rescan_interval: other.rescan_interval.or(self.rescan_interval),
+            track: other.track.or(self.track),
+            tombstones: other.tombstones.or(self.tombstones),
+            source: self.source.merge(other.source),
+        }
+    }
+}
+
+impl SourceSettings {
+    /// Override values set in `other`.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            output: other.output.or(self.output),
+            fs_timeout: other.fs_timeout.or(self.fs_timeout),
+            include: other.include.or(self.include),
+            exclude: other.exclude.or(self.exclude),
+            no_default_excludes: other.no_default_excludes.or(self.no_default_excludes),
+            max_file_size: other.max_file_size.or(self.max_file_size),
+            symlinks: other.symlinks.or(self.symlinks),
+            naming: other.naming.or(self.naming),
+        }
+    }
+
+    /// Expand variables and resolve relative output against `base`.
+    fn make_absolute(&mut self, base: &Path) -> Result<(), CliError> {
+        for output in self.output.iter_mut().flatten() {
+            *output = base.join(expand_path(output)?);
+        }
+
+        Ok(())
+    }
+}
+
+impl ConfigFile {
+    /// Expand variables and resolve relative paths against `base`.
+    fn make_absolute(&mut self, base: &Path) -> Result<(), CliError> {
+        self.settings.source.make_absolute(base)?;
+        make_sources_absolute(&mut self.sources, base)?;
+
+        for profile in self.profiles.values_mut() {
+            profile.settings.source.make_absolute(base)?;
+            make_sources_absolute(&mut profile.sources, base)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Expand variables and resolve relative source paths and outputs against `base`.
+fn make_sources_absolute(
+    sources: &mut [SourceEntry],
+    base: &Path,
+) -> Result<(), CliError> {
+    for source in sources {
+        source.path = base.join(expand_path(&source.path)?);
+        source.settings.make_absolute(base)?;
+    }
+
+    Ok(())
+}
+
+/// Read a single path or a list of paths.
+fn one_or_many<'de, D>(deserializer: D) -> Result<Option<Vec<PathBuf>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Paths {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+
+    Ok(Some(match Paths::deserialize(deserializer)? {
+        Paths::One(path) => vec![path],
+        Paths::Many(paths) => paths,
+    }))
+}
+
+/// Default configuration file in the user configuration folder.
+///
+/// `XDG_CONFIG_HOME` is resolved as in expanded paths, `APPDATA` replaces its
+/// default on Windows.
+pub fn default_config_path() -> Option<PathBuf> {
+    let folder = match env::var_os("APPDATA") {
+        Some(appdata) => xdg_folder("XDG_CONFIG_HOME", Path::new(&appdata), ""),
+        None => xdg_folder("XDG_CONFIG_HOME", &home()?, ".config"),
+    };
+
+    Some(folder.join(CONFIG_FILE))
+}
+
+/// Read a configuration file.
+///
+/// A missing default file is an empty configuration, an explicitly given one is
+/// an error.
+pub fn load_config(given: Option<&Path>) -> Result<ConfigFile, CliError> {
+    let (path, explicit) = match given {
+        Some(given_path) => (given_path.to_path_buf(), true),
+        None => match default_config_path() {
+            Some(default_path) => (default_path, false),
+            None => return Ok(ConfigFile::default()),
+        },
+    };
+
+    let content = match read_to_string(&path) {
+        Ok(content) => content,
+        Err(error) if !explicit && error.kind() == ErrorKind::NotFound => {
+            return Ok(ConfigFile::default());
+        }
+        Err(error) => return Err(CliError::ConfigRead { path, error }),
+    };
+
+    let mut config = toml::from_str::<ConfigFile>(&content).map_err(|error| {
+        CliError::ConfigParse {
+            path: path.clone(),
+            error,
+        }
+    })?;
+
+    let base = absolute(&path)
+        .ok()
+        .and_then(|file| file.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+    config.make_absolute(&base)?;
+
+    Ok(config)
+}
@@ -3,6 +3,11 @@ use core::fmt::{Display, Formatter, Result as FmtResult};
 use std::io::Error as IoError;
 use std::path::PathBuf;
 
+use toml::de::Error as TomlError;
+
+use super::NamingScheme;
+use crate::presets::PresetError;
+
 #[derive(Debug)]
 pub enum CliError {
     #[cfg(feature = "non-existing-option")]
@@ -13,18 +18,72 @@ pub enum CliError {
         error: IoError,
     },
     SourceNoFileStem(PathBuf),
+    SourceBrokenLink {
+        filename: PathBuf,
+        error: IoError,
+    },
     DestinationNotFolder(PathBuf),
+    DestinationMissing,
+    InvalidPattern(globset::Error),
+    /// Root of a glob is the folder of another source.
+    GlobSharingFolder(PathBuf),
+    InvalidRegex(regex::Error),
+    InvalidSize(String),
+    ConfigRead {
+        path: PathBuf,
+        error: IoError,
+    },
+    ConfigParse {
+        path: PathBuf,
+        error: TomlError,
+    },
+    ProfileMissing(String),
+    /// Sources sharing a folder have different debounce.
+    DebounceConflict {
+        folder: PathBuf,
+        first: u64,
+        second: u64,
+    },
+    /// Sources sharing a destination folder have different naming.
+    NamingConflict {
+        folder: PathBuf,
+        first: NamingScheme,
+        second: NamingScheme,
+    },
+    InvalidAlias(String),
+    /// Sources sharing a destination have the same alias.
+    DuplicateAlias(String),
+    UndefinedVariable {
+        name: String,
+        path: PathBuf,
+    },
+    Preset(PresetError),
 }
 
 impl Error for CliError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            Self::SourceNoAbsolute { error, .. } => Some(error),
+            Self::SourceNoAbsolute { error, .. }
+            | Self::SourceBrokenLink { error, .. }
+            | Self::ConfigRead { error, .. } => Some(error),
+            Self::ConfigParse { error, .. } => Some(error),
+            Self::InvalidPattern(error) => Some(error),
+            Self::InvalidRegex(error) => Some(error),
+            Self::Preset(error) => Some(error),
             #[cfg(feature = "non-existing-option")]
             Self::SourceNonexistent(_) => None,
             Self::SourceUnsupported(_)
             | Self::SourceNoFileStem(_)
-            | Self::DestinationNotFolder(_) => None,
+            | Self::DestinationNotFolder(_)
+            | Self::DestinationMissing
+            | Self::GlobSharingFolder(_)
+            | Self::InvalidSize(_)
+            | Self::ProfileMissing(_)
+            | Self::DebounceConflict { .. }
+            | Self::NamingConflict { .. }
+            | Self::InvalidAlias(_)
+            | Self::DuplicateAlias(_)
+            | Self::UndefinedVariable { .. } => None,
         }
     }
 }
@@ -48,9 +107,65 @@ impl Display for CliError {
             Self::SourceNoFileStem(filename) => {
                 write!(f, "Unable to get file name from {filename:?}.")
             }
+            Self::SourceBrokenLink { filename, error } => {
+                write!(f, "Unable to resolve symlink {filename:?}: {error}")
+            }
             Self::DestinationNotFolder(filename) => {
                 write!(f, "Destination path \"{filename:?}\" is not a folder.")
             }
+            Self::DestinationMissing => {
+                write!(f, "Destination folder is not set.")
+            }
+            Self::GlobSharingFolder(folder) => write!(
+                f,
+                "Glob source shares the folder {folder:?} with another source, \
+                 combine the patterns into one, e.g. `{{slot*.sav,*.ess}}`"
+            ),
+            Self::InvalidPattern(error) => {
+                write!(f, "Invalid file name pattern: {error}")
+            }
+            Self::InvalidRegex(error) => {
+                write!(f, "Invalid regular expression: {error}")
+            }
+            Self::InvalidSize(error) => write!(f, "Invalid file size: {error}"),
+            Self::ConfigRead { path, error } => {
+                write!(f, "Unable to read configuration {path:?}: {error}")
+            }
+            Self::ConfigParse { path, error } => {
+                write!(f, "Invalid configuration {path:?}: {error}")
+            }
+            Self::ProfileMissing(name) => {
+                write!(f, "Profile {name:?} is not configured")
+            }
+            Self::DebounceConflict {
+                folder,
+                first,
+                second,
+            } => write!(
+                f,
+                "Sources in folder {folder:?} are debounced by both {first} ms and {second} ms"
+            ),
+            Self::NamingConflict {
+                folder,
+                first,
+                second,
+            } => write!(
+                f,
+                "Backups in folder {folder:?} are named both {first:?} and {second:?}"
+            ),
+            Self::InvalidAlias(name) => {
+                write!(f, "Invalid source name {name:?}: it must be a file name")
+            }
+            Self::DuplicateAlias(name) => {
+                write!(f, "Source name {name:?} is used by several sources")
+            }
+            Self::Preset(error) => write!(f, "{error}"),
+            Self::UndefinedVariable { name, path } => {
+                write!(
+                    f,
+                    "Variable {name} used in {path:?} is not set, write $$ or %% for a literal $ or %"
+                )
+            }
         }
     }
 }
@@ -0,0 +1,151 @@
+use std::env;
+use std::path::{Path, PathBuf, is_separator};
+
+use super::CliError;
+use crate::utils::{home, xdg_folder};
+
+/// XDG base folders with their defaults under home if not set.
+const XDG_DEFAULTS: &[(&str, &str)] = &[
+    ("XDG_CONFIG_HOME", ".config"),
+    ("XDG_DATA_HOME", ".local/share"),
+    ("XDG_STATE_HOME", ".local/state"),
+    ("XDG_CACHE_HOME", ".cache"),
+];
+
+/// Expand `~` and variables written as `$NAME`, `${NAME}` or `%NAME%`.
+///
+/// `$$` and `%%` are a literal `$` and `%`. Unset XDG base folder variables take
+/// their defaults, other unset variables are an error. Paths which are not UTF-8
+/// are kept as is.
+pub fn expand_path(path: &Path) -> Result<PathBuf, CliError> {
+    let Some(value) = path.to_str() else {
+        return Ok(path.to_path_buf());
+    };
+
+    let undefined = |name: &str| CliError::UndefinedVariable {
+        name: name.to_owned(),
+        path: path.to_path_buf(),
+    };
+
+    let mut expanded = String::new();
+    let mut rest = value;
+
+    if let Some(after) = rest.strip_prefix('~') {
+        if after.is_empty() || after.starts_with(is_separator) {
+            let home = home().ok_or_else(|| undefined("HOME"))?;
+            expanded.push_str(&home.to_string_lossy());
+            rest = after;
+        }
+    }
+
+    while let Some((before, reference)) = rest
+        .find(['$', '%'])
+        .and_then(|start| rest.split_at_checked(start))
+    {
+        expanded.push_str(before);
+
+        if let Some((name, after)) = variable_at(reference) {
+            expanded.push_str(&variable(name).ok_or_else(|| undefined(name))?);
+            rest = after;
+            continue;
+        }
+
+        // Not a variable, `$$` and `%%` are kept as a single character.
+        let mut chars = reference.chars();
+        let marker = chars.next();
+        expanded.extend(marker);
+        let after = chars.as_str();
+        rest = marker
+            .and_then(|char| after.strip_prefix(char))
+            .unwrap_or(after);
+    }
+    expanded.push_str(rest);
+
+    let expanded_path = PathBuf::from(expanded);
+    if expanded_path != path {
+        log::debug!("Expanded {path:?} -> {expanded_path:?}");
+    }
+
+    Ok(expanded_path)
+}
+
+/// Variable name of the reference at the start of `value` and the rest after
+/// the reference.
+fn variable_at(value: &str) -> Option<(&str, &str)> {
+    let (name, rest) = if let Some(braced) = value.strip_prefix("${") {
+        braced.split_once('}')?
+    } else if let Some(percent) = value.strip_prefix('%') {
+        percent.split_once('%')?
+    } else {
+        let plain = value.strip_prefix('$')?;
+        let end = plain
+            .find(|char: char| !(char.is_ascii_alphanumeric() || char == '_'))
+            .unwrap_or(plain.len());
+        plain.split_at_checked(end)?
+    };
+
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_');
+
+    valid.then_some((name, rest))
+}
+
+/// Value of a variable, empty or unset XDG base folders have defaults.
+fn variable(name: &str) -> Option<String> {
+    let xdg_default = XDG_DEFAULTS
+        .iter()
+        .find(|(xdg, _)| *xdg == name)
+        .map(|(_, default)| default);
+
+    let value = match xdg_default {
+        Some(default) => xdg_folder(name, &home()?, default).into_os_string(),
+        None => env::var_os(name)?,
+    };
+    Some(value.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used, reason = "failed expansion fails the test")]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::expand_path;
+    use crate::args::CliError;
+
+    fn expand(path: &str) -> PathBuf {
+        expand_path(Path::new(path)).expect("path is expanded")
+    }
+
+    #[test]
+    fn keeps_escaped_characters() {
+        assert_eq!(
+            expand("saves/100%%_done_50%%"),
+            Path::new("saves/100%_done_50%")
+        );
+        assert_eq!(expand("saves/a$$b"), Path::new("saves/a$b"));
+        assert_eq!(expand("saves/$$HOME"), Path::new("saves/$HOME"));
+    }
+
+    #[test]
+    fn keeps_characters_not_starting_a_variable() {
+        assert_eq!(expand("saves/50%"), Path::new("saves/50%"));
+        assert_eq!(expand("saves/$1"), Path::new("saves/$1"));
+        assert_eq!(
+            expand("saves/\u{e9}%\u{fc}$\u{20ac}"),
+            Path::new("saves/\u{e9}%\u{fc}$\u{20ac}")
+        );
+    }
+
+    #[test]
+    fn rejects_unset_variables() {
+        for path in ["saves/%LITTLE_BECKY_UNSET%", "saves/${LITTLE_BECKY_UNSET}"] {
+            assert!(matches!(
+                expand_path(Path::new(path)),
+                Err(CliError::UndefinedVariable { name, .. }) if name == "LITTLE_BECKY_UNSET"
+            ));
+        }
+    }
+}
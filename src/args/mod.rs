@@ -1,19 +1,76 @@
 mod cli;
+mod config;
 mod error;
+mod expand;
 mod resolve;
 
-pub use cli::Verbosity;
+pub use cli::{NamingScheme, SymlinkPolicy, TrackMode, Verbosity, WatchMode};
+pub use config::ConfigFile;
 pub use error::CliError;
-pub use parser::parse_arguments;
-pub use resolve::{Args, Location, Source, verify_resolve};
+pub use parser::{parse_arguments, reparse_arguments};
+pub use resolve::{
+    Args, Command, CtlOptions, DiffOptions, ImportOptions, ImportPattern, ListOptions,
+    Location, PresetsOptions, ReindexOptions, RestoreOptions, Source, SourceOptions,
+    SourceRef, Version, WatchArgs, WatchSettings, resolve_source, verify_resolve,
+};
 
 mod parser {
-    use clap::Parser as _;
+    use std::env;
+
+    use clap::parser::ValueSource;
+    use clap::{ArgMatches, CommandFactory as _, FromArgMatches as _};
 
     use super::cli::CommandLineArgs;
-    use super::{Args, CliError, verify_resolve};
-    /// Parse and verify arguments.
-    pub fn parse_arguments() -> Result<Args, CliError> {
-        verify_resolve(CommandLineArgs::parse())
+    use super::{Args, CliError, Verbosity, verify_resolve};
+
+    /// Parsed arguments, resolved once logging is set up.
+    pub struct ParsedArguments {
+        matches: ArgMatches,
+        args: CommandLineArgs,
+    }
+
+    /// Parse arguments, exit on invalid ones.
+    pub fn parse_arguments() -> ParsedArguments {
+        ParsedArguments::new(CommandLineArgs::command().get_matches())
+    }
+
+    /// Parse and verify the same arguments again, e.g. to reload configuration.
+    pub fn reparse_arguments() -> Result<Args, CliError> {
+        ParsedArguments::new(
+            CommandLineArgs::command().get_matches_from(env::args_os()),
+        )
+        .resolve()
+    }
+
+    impl ParsedArguments {
+        fn new(matches: ArgMatches) -> Self {
+            let args = CommandLineArgs::from_arg_matches(&matches)
+                .unwrap_or_else(|error| error.exit());
+            Self { matches, args }
+        }
+
+        pub fn log_level(&self) -> Verbosity {
+            self.args.log_level.clone()
+        }
+
+        /// Verify and resolve arguments.
+        pub fn resolve(self) -> Result<Args, CliError> {
+            // Flags given explicitly override the configuration.
+            let matches = self
+                .matches
+                .subcommand_matches("doctor")
+                .unwrap_or(&self.matches);
+            let given =
+                |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+            // Filters given after a source apply only to it.
+            let positions = |id: &str| {
+                matches
+                    .indices_of(id)
+                    .filter(|_| given(id))
+                    .map(Iterator::collect)
+                    .unwrap_or_default()
+            };
+            verify_resolve(self.args, &given, &positions)
+        }
     }
 }
@@ -1,119 +1,1236 @@
-use std::path::{Path, PathBuf, absolute};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use std::ffi::OsStr;
+use std::fs::canonicalize;
+use std::path::{Path, PathBuf, absolute, is_separator};
 
 pub use crate::utils::ParentPath as _;
 
-use super::cli::CommandLineArgs;
-use super::{CliError, Verbosity};
+use super::cli::{
+    CommandArgs, CommandLineArgs, CtlAction, CtlArgs, DEFAULT_FS_TIMEOUT, DiffArgs,
+    ImportArgs, ListArgs, PresetsAction, ReindexArgs, RestoreArgs, WatchCliArgs,
+};
+use super::{CliError, NamingScheme, SymlinkPolicy, TrackMode, WatchMode};
+use regex::Regex;
+
+use super::ConfigFile;
+use super::config::{
+    Profile, Settings, SourceEntry, SourceSettings, default_config_path, load_config,
+};
+use super::expand::expand_path;
+use crate::control::{Request, default_socket};
+use crate::filter::{DEFAULT_EXCLUDES, SourceFilter, SourcePattern, parse_size};
+use crate::presets::{self, PresetError};
+use crate::utils::home;
 
 pub struct Args {
-    pub fs_timeout: u64,
-    pub log_level: Verbosity,
+    /// Log writes instead of doing them.
+    pub dry_run: bool,
+    /// Format of version ids in backup file names.
+    pub naming: NamingScheme,
+    pub command: Command,
+}
+
+/// Resolved command to run.
+pub enum Command {
+    Watch(Box<WatchArgs>),
+    /// Print effective settings of a normal run, they are not validated.
+    PrintConfig(Box<ConfigFile>),
+    /// Check sources and destinations of a normal run.
+    Doctor(Box<WatchArgs>),
+    List(ListOptions),
+    Restore(RestoreOptions),
+    Diff(DiffOptions),
+    Reindex(ReindexOptions),
+    Import(ImportOptions),
+    Ctl(CtlOptions),
+    Presets(PresetsOptions),
+}
+
+/// Arguments to watch sources.
+pub struct WatchArgs {
+    /// All destination folder roots, the one of runtime sources first.
+    pub destinations: Vec<PathBuf>,
+    pub watch: WatchSettings,
+    /// Back up once and exit.
+    pub once: bool,
+    /// Control socket to listen on.
+    pub control: Option<PathBuf>,
+    /// Configuration file to reload on changes.
+    pub config_path: Option<PathBuf>,
+    /// Settings of sources added at runtime.
+    pub defaults: SourceOptions,
     pub sources: Vec<Source>,
 }
 
+/// Resolved settings of a source.
 #[derive(Debug, Clone)]
+pub struct SourceOptions {
+    /// Destination folder roots, at least one.
+    pub destinations: Vec<PathBuf>,
+    pub filter: Arc<SourceFilter>,
+    pub symlinks: SymlinkPolicy,
+    pub naming: NamingScheme,
+    /// Debounce of the source folder in milliseconds.
+    pub fs_timeout: u64,
+}
+
+/// Options of `list` command.
+#[derive(Debug)]
+pub struct ListOptions {
+    pub destination: PathBuf,
+    /// Sources to list, all backups if empty.
+    pub sources: Vec<SourceRef>,
+    pub json: bool,
+    pub since: Option<u128>,
+    pub until: Option<u128>,
+}
+
+/// Options of `restore` command.
+#[derive(Debug)]
+pub struct RestoreOptions {
+    pub source: SourceRef,
+    pub version: Version,
+}
+
+/// Options of `diff` command.
+#[derive(Debug)]
+pub struct DiffOptions {
+    pub source: SourceRef,
+    pub id_a: u64,
+    pub id_b: u64,
+}
+
+/// Options of `reindex` command.
+#[derive(Debug)]
+pub struct ReindexOptions {
+    pub destination: PathBuf,
+    /// Naming of renumbered versions, each folder keeps its naming if not set.
+    pub to: Option<NamingScheme>,
+}
+
+/// Options of `import` command.
+#[derive(Debug)]
+pub struct ImportOptions {
+    pub pattern: ImportPattern,
+    /// Move files instead of copying them.
+    pub move_files: bool,
+    /// Folder with files to import.
+    pub folder: PathBuf,
+    pub source: SourceRef,
+}
+
+/// Source of a command given as it's watched, `[NAME=]SOURCE[:DEST]`.
+#[derive(Debug, Clone)]
+pub struct SourceRef {
+    pub path: PathBuf,
+    /// Alias the source is backed up by.
+    pub name: Option<String>,
+    /// Destination root, `DEST` if given, otherwise `--output`.
+    pub destination: PathBuf,
+}
+
+/// Options of `ctl` command.
+#[derive(Debug)]
+pub struct CtlOptions {
+    pub socket: PathBuf,
+    /// Request with absolute source paths.
+    pub request: Request,
+}
+
+/// Options of `presets search` command.
+#[derive(Debug)]
+pub struct PresetsOptions {
+    /// Text to search, all presets if not set.
+    pub query: Option<String>,
+}
+
+/// File names of foreign backups.
+#[derive(Debug, Clone)]
+pub enum ImportPattern {
+    /// Numbered copies, e.g. `save (1).sav`.
+    Numbered,
+    /// Backup suffixes, e.g. `save.sav.bak`, `save.sav.bak3`.
+    Bak,
+    /// Embedded dates, e.g. `save-2025-01-02.sav`, `save_2025-01-02 13-45.sav`.
+    Dated,
+    /// Custom regex, named groups `date` and `n` order files.
+    Regex(Regex),
+}
+
+/// Version selected from a source history.
+#[derive(Debug, Clone, Copy)]
+pub enum Version {
+    /// Backup with the id.
+    Id(u64),
+    /// The last backup.
+    Latest,
+    /// The last version modified at or before the time.
+    At(u128),
+    /// The N-th backup before the latest one.
+    Previous(usize),
+}
+
+/// Settings for watching sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchSettings {
+    /// Timeout for FS notify debouncer in milliseconds.
+    pub fs_timeout: u64,
+    /// How renamed files are followed.
+    pub track: TrackMode,
+    /// Write tombstone files for deleted sources.
+    pub tombstones: bool,
+    /// Native notifications or polling.
+    pub watch_mode: WatchMode,
+    /// Poll interval in milliseconds.
+    pub poll_interval: u64,
+    /// Compare file contents while polling.
+    pub poll_compare_contents: bool,
+    /// Interval of reconciliation scans in seconds.
+    pub rescan_interval: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Source {
     File(Location),
     Folder(Location),
+    /// Files matching a glob under its root folder, the filter holds the pattern.
+    Glob(Location),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Location {
     pub source: PathBuf,
-    pub destination: PathBuf,
+    /// Destination folders, each of them has its own state.
+    pub destinations: Vec<PathBuf>,
+    /// Alias replacing the file stem of a file source or the folder name of a
+    /// folder source, matches of a glob source are backed up under it.
+    pub name: Option<String>,
+    /// Filter for files under a folder source.
+    pub filter: Arc<SourceFilter>,
+    /// How symbolic links are handled.
+    pub symlinks: SymlinkPolicy,
+    /// Format of version ids in the destination.
+    pub naming: NamingScheme,
+    /// Debounce of the source folder in milliseconds.
+    pub fs_timeout: u64,
 }
 
 /// Verify and resolve arguments.
-pub fn verify_resolve(args: CommandLineArgs) -> Result<Args, CliError> {
+///
+/// `given` tells if a flag was given on the command line, `positions` returns
+/// command line indices of its values.
+pub fn verify_resolve(
+    args: CommandLineArgs,
+    given: &dyn Fn(&str) -> bool,
+    positions: &dyn Fn(&str) -> Vec<usize>,
+) -> Result<Args, CliError> {
+    let dry_run = args.dry_run;
+    let mut naming = args.naming;
+
+    let command = match args.command {
+        Some(CommandArgs::List(list_args)) => Command::List(resolve_list(&list_args)?),
+        Some(CommandArgs::Restore(restore_args)) => {
+            Command::Restore(resolve_restore(&restore_args)?)
+        }
+        Some(CommandArgs::Diff(diff_args)) => Command::Diff(resolve_diff(&diff_args)?),
+        Some(CommandArgs::Reindex(reindex_args)) => {
+            Command::Reindex(resolve_reindex(&reindex_args)?)
+        }
+        Some(CommandArgs::Import(import_args)) => {
+            Command::Import(resolve_import(&import_args)?)
+        }
+        Some(CommandArgs::Ctl(ctl_args)) => Command::Ctl(resolve_ctl(ctl_args)?),
+        Some(CommandArgs::Doctor(doctor_args)) => {
+            let watch_args =
+                resolve_watch(doctor_args, naming, given, positions, true)?;
+            naming = watch_args.defaults.naming;
+            Command::Doctor(Box::new(watch_args))
+        }
+        Some(CommandArgs::Presets(presets_args)) => match presets_args.action {
+            PresetsAction::Search { query } => {
+                Command::Presets(PresetsOptions { query })
+            }
+        },
+        None if args.watch.print_config => {
+            let mut watch_args = args.watch;
+            let (_, config) = merge_watch(&mut watch_args, naming, given, positions)?;
+            Command::PrintConfig(Box::new(config))
+        }
+        None => {
+            let watch_args =
+                resolve_watch(args.watch, naming, given, positions, false)?;
+            naming = watch_args.defaults.naming;
+            Command::Watch(Box::new(watch_args))
+        }
+    };
+
+    Ok(Args {
+        dry_run,
+        naming,
+        command,
+    })
+}
+
+/// Resolve arguments to watch sources.
+///
+/// Unavailable destinations are kept for `doctor` to report them.
+fn resolve_watch(
+    mut args: WatchCliArgs,
+    naming: NamingScheme,
+    given: &dyn Fn(&str) -> bool,
+    positions: &dyn Fn(&str) -> Vec<usize>,
+    keep_unavailable: bool,
+) -> Result<WatchArgs, CliError> {
+    let (config_path, config) = merge_watch(&mut args, naming, given, positions)?;
+    let ConfigFile {
+        settings,
+        sources: entries,
+        ..
+    } = config;
+
+    // Sources added at runtime use the global output, or the first configured one.
+    let mut global = settings.source.clone();
+    global.output = global.output.or_else(|| {
+        entries
+            .iter()
+            .find_map(|entry| entry.settings.output.clone())
+    });
+    let defaults = source_options(&global, keep_unavailable)?;
+
+    let specs = entries
+        .iter()
+        .map(|entry| {
+            if let Some(name) = &entry.name {
+                check_alias(name)?;
+            }
+            Ok((
+                entry.path.clone(),
+                entry.name.clone(),
+                source_options(&entry.settings, keep_unavailable)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, CliError>>()?;
+
+    let mut destinations = defaults.destinations.clone();
+    for destination in specs
+        .iter()
+        .flat_map(|(_, _, options)| &options.destinations)
+    {
+        if !destinations.contains(destination) {
+            destinations.push(destination.clone());
+        }
+    }
+
     let sources = convert_sources(
-        &args.destination,
-        &args.sources,
+        &specs,
         #[cfg(feature = "non-existing-option")]
         args.register_nonexistent,
     )?;
+    check_shared_settings(&sources)?;
 
-    Ok(Args {
-        fs_timeout: args.fs_timeout,
-        log_level: args.log_level,
+    let control = args
+        .control_socket
+        .map(|socket| absolute_path(&socket))
+        .transpose()?
+        .or_else(|| {
+            args.control
+                .then(|| default_socket(defaults.primary_destination()))
+        });
+
+    let watch = WatchSettings {
+        fs_timeout: defaults.fs_timeout,
+        track: settings.track.unwrap_or_default(),
+        tombstones: settings.tombstones.unwrap_or_default(),
+        watch_mode: settings.watch_mode.unwrap_or_default(),
+        poll_interval: settings.poll_interval.unwrap_or(args.poll_interval),
+        poll_compare_contents: settings.poll_compare_contents.unwrap_or_default(),
+        rescan_interval: settings.rescan_interval,
+    };
+
+    Ok(WatchArgs {
+        destinations,
+        once: args.once,
+        control,
+        config_path,
+        defaults,
+        watch,
         sources,
     })
 }
 
+/// Merge settings and sources to watch, they are validated once resolved.
+///
+/// Settings are merged from defaults, the configuration file, the profile, the
+/// source entry and flags given explicitly, later ones win. Sources given as
+/// arguments replace configured ones, filters given after a source argument
+/// extend the filters of that source only.
+///
+/// Return the configuration file to reload and the effective settings.
+fn merge_watch(
+    args: &mut WatchCliArgs,
+    naming: NamingScheme,
+    given: &dyn Fn(&str) -> bool,
+    positions: &dyn Fn(&str) -> Vec<usize>,
+) -> Result<(Option<PathBuf>, ConfigFile), CliError> {
+    let explicit_config = args.config.as_deref().map(absolute_path).transpose()?;
+    let config = load_config(explicit_config.as_deref())?;
+    let config_path = explicit_config.or_else(default_config_path);
+    let profile = match &args.profile {
+        Some(name) => config
+            .profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| CliError::ProfileMissing(name.clone()))?,
+        None => Profile::default(),
+    };
+
+    let outputs = args
+        .destination
+        .iter()
+        .map(|destination| expand_path(destination))
+        .collect::<Result<Vec<_>, _>>()?;
+    let source_positions = positions("sources");
+    let includes =
+        SourceFilters::split(&args.include, &positions("include"), &source_positions);
+    let excludes =
+        SourceFilters::split(&args.exclude, &positions("exclude"), &source_positions);
+    args.include.clone_from(&includes.shared);
+    args.exclude.clone_from(&excludes.shared);
+    let mut explicit = cli_settings(args, naming, &outputs, given);
+    explicit.source.include =
+        explicit.source.include.filter(|values| !values.is_empty());
+    explicit.source.exclude =
+        explicit.source.exclude.filter(|values| !values.is_empty());
+    let settings = cli_settings(args, naming, &outputs, &|_| true)
+        .merge(config.settings)
+        .merge(profile.settings)
+        .merge(explicit.clone());
+
+    // A destination of a source argument overrides `--output`.
+    let from_cli = !args.sources.is_empty() || !args.presets.is_empty();
+    let entries = if from_cli {
+        let mut entries = args
+            .sources
+            .iter()
+            .enumerate()
+            .map(|(index, arg)| {
+                let mut entry = parse_source_arg(arg)?;
+                entry.settings.include = includes.of_source(index);
+                entry.settings.exclude = excludes.of_source(index);
+                Ok(entry)
+            })
+            .collect::<Result<Vec<_>, CliError>>()?;
+        for query in &args.presets {
+            entries.extend(preset_entries(query)?);
+        }
+        entries
+    } else {
+        config
+            .sources
+            .into_iter()
+            .chain(profile.sources)
+            .collect::<Vec<_>>()
+    };
+
+    let sources = entries
+        .into_iter()
+        .map(|entry| SourceEntry {
+            settings: if from_cli {
+                settings
+                    .source
+                    .clone()
+                    .merge(explicit.source.clone())
+                    .merge(entry.settings)
+            } else {
+                settings
+                    .source
+                    .clone()
+                    .merge(entry.settings)
+                    .merge(explicit.source.clone())
+            },
+            path: entry.path,
+            name: entry.name,
+        })
+        .collect::<Vec<_>>();
+
+    Ok((
+        config_path,
+        ConfigFile {
+            settings,
+            sources,
+            profiles: BTreeMap::new(),
+        },
+    ))
+}
+
+/// Check that sources sharing a watched folder share its debounce and sources
+/// sharing a destination folder share its naming.
+fn check_shared_settings(sources: &[Source]) -> Result<(), CliError> {
+    let mut debounces = BTreeMap::new();
+    let mut namings = BTreeMap::new();
+
+    for source in sources {
+        let location = source.location();
+        let folder = match source {
+            Source::File(_) => location.source.parent_path(),
+            Source::Folder(_) | Source::Glob(_) => &location.source,
+        };
+        let debounce = *debounces
+            .entry(folder.to_path_buf())
+            .or_insert(location.fs_timeout);
+        if debounce != location.fs_timeout {
+            return Err(CliError::DebounceConflict {
+                folder: folder.to_path_buf(),
+                first: debounce,
+                second: location.fs_timeout,
+            });
+        }
+
+        for destination in &location.destinations {
+            let naming = *namings
+                .entry(destination.clone())
+                .or_insert(location.naming);
+            if naming != location.naming {
+                return Err(CliError::NamingConflict {
+                    folder: destination.clone(),
+                    first: naming,
+                    second: location.naming,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Values of a filter flag split by the source arguments they follow.
+struct SourceFilters {
+    /// Values given before the first source, they apply to all sources.
+    shared: Vec<String>,
+    /// Values given after each source.
+    sources: Vec<Vec<String>>,
+}
+
+impl SourceFilters {
+    /// Split `values` by their command line `positions` relative to
+    /// `source_positions`.
+    fn split(
+        values: &[String],
+        positions: &[usize],
+        source_positions: &[usize],
+    ) -> Self {
+        let mut filters = Self {
+            shared: vec![],
+            sources: vec![vec![]; source_positions.len()],
+        };
+
+        for (value, position) in values.iter().zip(positions) {
+            let following = source_positions
+                .iter()
+                .take_while(|&source| source < position)
+                .count();
+            match following.checked_sub(1) {
+                Some(index) => filters.sources.get_mut(index),
+                None => Some(&mut filters.shared),
+            }
+            .into_iter()
+            .for_each(|target| target.push(value.clone()));
+        }
+
+        filters
+    }
+
+    /// Shared values extended by values of the source, if it has any.
+    fn of_source(&self, index: usize) -> Option<Vec<String>> {
+        self.sources
+            .get(index)
+            .filter(|values| !values.is_empty())
+            .map(|values| self.shared.iter().chain(values).cloned().collect())
+    }
+}
+
+/// Settings given as flags, all of them or only explicitly given ones.
+///
+/// `outputs` are expanded `--output` values.
+fn cli_settings(
+    args: &WatchCliArgs,
+    naming: NamingScheme,
+    outputs: &[PathBuf],
+    given: &dyn Fn(&str) -> bool,
+) -> Settings {
+    Settings {
+        watch_mode: given("watch_mode").then_some(args.watch_mode),
+        poll_interval: given("poll_interval").then_some(args.poll_interval),
+        poll_compare_contents: given("poll_compare_contents")
+            .then_some(args.poll_compare_contents),
+        rescan_interval: args.rescan_interval.filter(|_| given("rescan_interval")),
+        track: given("track").then_some(args.track),
+        tombstones: given("tombstones").then_some(args.tombstones),
+        source: SourceSettings {
+            output: given("destination")
+                .then(|| outputs.to_vec())
+                .filter(|given_outputs| !given_outputs.is_empty()),
+            fs_timeout: given("fs_timeout").then_some(args.fs_timeout),
+            include: given("include").then(|| args.include.clone()),
+            exclude: given("exclude").then(|| args.exclude.clone()),
+            no_default_excludes: given("no_default_excludes")
+                .then_some(args.no_default_excludes),
+            max_file_size: args
+                .max_file_size
+                .filter(|_| given("max_file_size"))
+                .map(|size| size.to_string()),
+            symlinks: given("symlinks").then_some(args.symlinks),
+            naming: given("naming").then_some(naming),
+        },
+    }
+}
+
+/// Resolve merged settings of a source.
+fn source_options(
+    settings: &SourceSettings,
+    keep_unavailable: bool,
+) -> Result<SourceOptions, CliError> {
+    let outputs = settings
+        .output
+        .as_ref()
+        .filter(|outputs| !outputs.is_empty())
+        .ok_or(CliError::DestinationMissing)?;
+
+    let default_excludes = if settings.no_default_excludes.unwrap_or_default() {
+        &[][..]
+    } else {
+        DEFAULT_EXCLUDES
+    };
+    let max_file_size = settings
+        .max_file_size
+        .as_deref()
+        .map(parse_size)
+        .transpose()
+        .map_err(CliError::InvalidSize)?;
+
+    let filter = SourceFilter::new(
+        settings.include.iter().flatten(),
+        default_excludes
+            .iter()
+            .copied()
+            .chain(settings.exclude.iter().flatten().map(String::as_str)),
+        max_file_size,
+    )
+    .map_err(CliError::InvalidPattern)?;
+
+    Ok(SourceOptions {
+        destinations: resolve_destinations(outputs, keep_unavailable)?,
+        filter: Arc::new(filter),
+        symlinks: settings.symlinks.unwrap_or_default(),
+        naming: settings.naming.unwrap_or_default(),
+        fs_timeout: settings.fs_timeout.unwrap_or(DEFAULT_FS_TIMEOUT),
+    })
+}
+
+/// Resolve arguments of `list` command.
+fn resolve_list(args: &ListArgs) -> Result<ListOptions, CliError> {
+    let destination = resolve_destination(&expand_path(&args.destination)?)?;
+
+    let sources = args
+        .sources
+        .iter()
+        .map(|source| resolve_source_ref(source, &destination))
+        .collect::<Result<_, _>>()?;
+
+    Ok(ListOptions {
+        destination,
+        sources,
+        json: args.json,
+        since: args.since,
+        until: args.until,
+    })
+}
+
+/// Resolve arguments of `restore` command.
+fn resolve_restore(args: &RestoreArgs) -> Result<RestoreOptions, CliError> {
+    let destination = resolve_destination(&expand_path(&args.destination)?)?;
+
+    let source = resolve_source_ref(&args.source, &destination)?;
+
+    // Exactly one of them is set, it's enforced by the argument group.
+    let version = match (args.id, args.at, args.previous) {
+        (Some(id), _, _) => Version::Id(id),
+        (None, Some(at), _) => Version::At(at),
+        (None, None, Some(previous)) => Version::Previous(previous),
+        (None, None, None) => {
+            debug_assert!(args.latest, "Version must be selected");
+            Version::Latest
+        }
+    };
+
+    Ok(RestoreOptions { source, version })
+}
+
+/// Resolve arguments of `diff` command.
+fn resolve_diff(args: &DiffArgs) -> Result<DiffOptions, CliError> {
+    let destination = resolve_destination(&expand_path(&args.destination)?)?;
+
+    Ok(DiffOptions {
+        source: resolve_source_ref(&args.source, &destination)?,
+        id_a: args.id_a,
+        id_b: args.id_b,
+    })
+}
+
+/// Resolve arguments of `reindex` command.
+fn resolve_reindex(args: &ReindexArgs) -> Result<ReindexOptions, CliError> {
+    Ok(ReindexOptions {
+        destination: resolve_destination(&expand_path(&args.destination)?)?,
+        to: args.to,
+    })
+}
+
+/// Resolve arguments of `import` command.
+fn resolve_import(args: &ImportArgs) -> Result<ImportOptions, CliError> {
+    let pattern = match args.pattern.as_str() {
+        "numbered" => ImportPattern::Numbered,
+        "bak" => ImportPattern::Bak,
+        "dated" => ImportPattern::Dated,
+        pattern => {
+            ImportPattern::Regex(Regex::new(pattern).map_err(CliError::InvalidRegex)?)
+        }
+    };
+
+    let folder = absolute_path(&args.folder)?;
+    if !folder.is_dir() {
+        return Err(CliError::SourceUnsupported(folder));
+    }
+
+    let destination = resolve_destination(&expand_path(&args.destination)?)?;
+
+    Ok(ImportOptions {
+        pattern,
+        move_files: args.move_files,
+        folder,
+        source: resolve_source_ref(&args.source, &destination)?,
+    })
+}
+
+/// Resolve a source of a command given as it's watched, `DEST` replaces
+/// `default_destination`.
+fn resolve_source_ref(
+    arg: &Path,
+    default_destination: &Path,
+) -> Result<SourceRef, CliError> {
+    let entry = parse_source_arg(arg)?;
+    if let Some(name) = &entry.name {
+        check_alias(name)?;
+    }
+
+    let destination = match entry.settings.output.into_iter().flatten().next() {
+        Some(output) => resolve_destination(&output)?,
+        None => default_destination.to_path_buf(),
+    };
+
+    Ok(SourceRef {
+        path: absolute_path(&entry.path)?,
+        name: entry.name,
+        destination,
+    })
+}
+
+/// Resolve arguments of `ctl` command.
+fn resolve_ctl(args: CtlArgs) -> Result<CtlOptions, CliError> {
+    // One of them is set, it's enforced by the argument group.
+    let socket = match (args.socket, args.destination) {
+        (Some(socket), _) => absolute_path(&socket)?,
+        (None, Some(destination)) => {
+            default_socket(&resolve_destination(&expand_path(&destination)?)?)
+        }
+        (None, None) => return Err(CliError::DestinationMissing),
+    };
+
+    let source = |path: PathBuf| absolute_path(&path);
+
+    let request = match args.action {
+        CtlAction::Status => Request::Status,
+        CtlAction::Add { path } => Request::Add {
+            path: source(path)?,
+        },
+        CtlAction::Remove { path } => Request::Remove {
+            path: source(path)?,
+        },
+        CtlAction::Pause { path } => Request::Pause {
+            path: source(path)?,
+        },
+        CtlAction::Resume { path } => Request::Resume {
+            path: source(path)?,
+        },
+        CtlAction::Backup { path } => Request::Backup {
+            path: source(path)?,
+        },
+        CtlAction::Shutdown => Request::Shutdown,
+    };
+
+    Ok(CtlOptions { socket, request })
+}
+
+/// Resolve a source added to a running watcher.
+pub fn resolve_source(
+    path: &Path,
+    options: &SourceOptions,
+) -> Result<Source, CliError> {
+    convert_sources(
+        &[(path.to_path_buf(), None, options.clone())],
+        #[cfg(feature = "non-existing-option")]
+        false,
+    )?
+    .pop()
+    .ok_or_else(|| CliError::SourceUnsupported(path.to_path_buf()))
+}
+
+/// Expand variables in a path given as an argument and make it absolute.
+fn absolute_path(path: &Path) -> Result<PathBuf, CliError> {
+    let expanded = expand_path(path)?;
+    absolute(&expanded).map_err(|error| CliError::SourceNoAbsolute {
+        filename: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Resolve absolute destination path, it must be an existing folder.
+fn resolve_destination(args_destination: &Path) -> Result<PathBuf, CliError> {
+    let destination = absolute_destination(args_destination)?;
+
+    if !destination.is_dir() {
+        return Err(CliError::DestinationNotFolder(destination));
+    }
+
+    Ok(destination)
+}
+
+/// Resolve absolute destination path, it may not exist.
+fn absolute_destination(args_destination: &Path) -> Result<PathBuf, CliError> {
+    absolute(args_destination).map_err(|error| CliError::SourceNoAbsolute {
+        filename: args_destination.to_path_buf(),
+        error,
+    })
+}
+
+/// Resolve destinations of a watched source.
+///
+/// A missing destination is skipped while another one is available, e.g. an
+/// unmounted drive. It gets only the current version on the next start.
+/// With `keep_unavailable` no destination is skipped.
+fn resolve_destinations(
+    outputs: &[PathBuf],
+    keep_unavailable: bool,
+) -> Result<Vec<PathBuf>, CliError> {
+    let mut destinations: Vec<PathBuf> = vec![];
+    let mut first_error = None;
+
+    for output in outputs {
+        let resolved = if keep_unavailable {
+            absolute_destination(output)
+        } else {
+            resolve_destination(output)
+        };
+        match resolved {
+            Ok(destination) if destinations.contains(&destination) => {}
+            Ok(destination) => destinations.push(destination),
+            Err(error) => {
+                if outputs.len() > 1 {
+                    log::warn!("Skipping unavailable destination: {error}");
+                }
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+
+    match first_error {
+        Some(error) if destinations.is_empty() => Err(error),
+        _ => Ok(destinations),
+    }
+}
+
+impl SourceOptions {
+    /// Destination of the control socket.
+    pub fn primary_destination(&self) -> &Path {
+        self.destinations
+            .first()
+            .map_or_else(|| Path::new(""), PathBuf::as_path)
+    }
+}
+
 impl Source {
     pub const fn source(&self) -> &PathBuf {
         match self {
-            Self::File(location) | Self::Folder(location) => &location.source,
+            Self::File(location) | Self::Folder(location) | Self::Glob(location) => {
+                &location.source
+            }
+        }
+    }
+
+    pub const fn location(&self) -> &Location {
+        match self {
+            Self::File(location) | Self::Folder(location) | Self::Glob(location) => {
+                location
+            }
         }
     }
 }
 
-fn convert_sources(
-    args_destination: &Path,
-    sources: &[PathBuf],
-    #[cfg(feature = "non-existing-option")] register_nonexistent: bool,
-) -> Result<Vec<Source>, CliError> {
-    let destination =
-        absolute(args_destination).map_err(|error| CliError::SourceNoAbsolute {
-            filename: args_destination.to_path_buf(),
-            error,
-        })?;
+impl Location {
+    /// Folder source at the path of a missing file source, which appeared as a
+    /// folder. Backups go to a subfolder named by the alias or the folder.
+    pub fn as_folder(&self) -> Option<Self> {
+        let stem = match &self.name {
+            Some(name) => OsStr::new(name),
+            None => self.source.file_stem()?,
+        };
 
-    if !destination.is_dir() {
-        return Err(CliError::DestinationNotFolder(destination));
+        Some(Self {
+            destinations: self
+                .destinations
+                .iter()
+                .map(|destination| destination.join(stem))
+                .collect(),
+            ..self.clone()
+        })
+    }
+
+    /// Folder locations of a glob source from `start` down which may contain
+    /// matches.
+    ///
+    /// Matches are backed up under the same relative folder in destinations.
+    pub fn glob_folders(&self, start: &Path) -> Vec<Self> {
+        let Some(pattern) = self.filter.pattern() else {
+            return vec![];
+        };
+
+        pattern
+            .folders(start)
+            .into_iter()
+            .map(|folder| {
+                let relative = folder.strip_prefix(&self.source).unwrap_or(&folder);
+                Self {
+                    destinations: self
+                        .destinations
+                        .iter()
+                        .map(|destination| destination.join(relative))
+                        .map(|destination| destination.components().collect())
+                        .collect(),
+                    source: folder,
+                    name: None,
+                    ..self.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Split a source argument `NAME=SOURCE[:DEST]` into its parts and expand them.
+///
+/// An argument without a name before `=` is a plain source path.
+fn parse_source_arg(arg: &Path) -> Result<SourceEntry, CliError> {
+    let plain = || {
+        Ok(SourceEntry {
+            path: expand_path(arg)?,
+            name: None,
+            settings: SourceSettings::default(),
+        })
+    };
+
+    let Some((name, rest)) = arg.to_str().and_then(|text| text.split_once('=')) else {
+        return plain();
+    };
+    if name.is_empty() || name.contains(is_separator) {
+        return plain();
+    }
+
+    let (source, destination) = split_destination(rest);
+    Ok(SourceEntry {
+        path: expand_path(Path::new(source))?,
+        name: Some(name.to_owned()),
+        settings: SourceSettings {
+            output: destination
+                .map(|output| expand_path(Path::new(output)))
+                .transpose()?
+                .map(|output| vec![output]),
+            ..SourceSettings::default()
+        },
+    })
+}
+
+/// Aliased sources of a preset found by its name or application id.
+fn preset_entries(query: &str) -> Result<Vec<SourceEntry>, CliError> {
+    let preset = presets::find(query).map_err(CliError::Preset)?;
+    let home = home().ok_or(CliError::Preset(PresetError::NoHome))?;
+    let sources = preset.locate(&home).map_err(CliError::Preset)?;
+
+    Ok(sources
+        .into_iter()
+        .map(|(path, name)| {
+            log::info!("Preset {:?} found at {path:?}", preset.name);
+            SourceEntry {
+                path,
+                name: Some(name),
+                settings: SourceSettings::default(),
+            }
+        })
+        .collect())
+}
+
+/// Split `SOURCE[:DEST]` at the last colon which is not a drive letter one.
+fn split_destination(value: &str) -> (&str, Option<&str>) {
+    let is_drive = |index: usize| {
+        let mut before = value.get(..index).unwrap_or_default().chars().rev();
+        let mut after = value.get(index + 1..).unwrap_or_default().chars();
+
+        before
+            .next()
+            .is_some_and(|letter| letter.is_ascii_alphabetic())
+            && before.next().is_none_or(|previous| previous == ':')
+            && after.next().is_some_and(is_separator)
+    };
+
+    value
+        .rmatch_indices(':')
+        .find(|(index, _)| !is_drive(*index))
+        .and_then(|(index, _)| value.split_at_checked(index))
+        .map_or((value, None), |(source, rest)| {
+            (source, rest.strip_prefix(':'))
+        })
+}
+
+/// Check that a source alias is usable as a file name.
+fn check_alias(name: &str) -> Result<(), CliError> {
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.contains(is_separator)
+        || name.contains(':')
+    {
+        return Err(CliError::InvalidAlias(name.to_owned()));
     }
 
+    Ok(())
+}
+
+/// Convert sources to watch with their names and options.
+fn convert_sources(
+    sources: &[(PathBuf, Option<String>, SourceOptions)],
+    #[cfg(feature = "non-existing-option")] register_nonexistent: bool,
+) -> Result<Vec<Source>, CliError> {
     let mut result: Vec<Source> = vec![];
 
-    for source_path in sources {
-        let source =
+    for (index, (source_path, name, options)) in sources.iter().enumerate() {
+        check_duplicate_alias(sources.iter().take(index), name.as_deref(), options)?;
+
+        let path =
             absolute(source_path).map_err(|error| CliError::SourceNoAbsolute {
                 filename: source_path.clone(),
                 error,
             })?;
 
+        // Folder name comes from the alias or from the path as given, even if
+        // it's a link.
+        let stem = match name {
+            Some(alias) => alias.into(),
+            None => path
+                .file_stem()
+                .ok_or_else(|| CliError::SourceNoFileStem(source_path.clone()))?
+                .to_owned(),
+        };
+
+        if path.symlink_metadata().is_err() {
+            if let Some(split) = SourcePattern::split(&path) {
+                let pattern = split.map_err(CliError::InvalidPattern)?;
+                let glob = glob_source(pattern, name.as_deref(), options, &result)?;
+                result.push(glob);
+                continue;
+            }
+        }
+
+        let is_symlink = path
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.file_type().is_symlink());
+        let as_link = is_symlink && options.symlinks == SymlinkPolicy::CopyLink;
+
+        let Some(source) = apply_symlink_policy(path, options.symlinks, is_symlink)?
+        else {
+            continue;
+        };
+
         if result.iter().any(|known| known.source() == &source) {
             log::warn!("Skipping duplicated {source:?}");
             continue;
         }
 
-        let target_base = destination.clone();
+        let location = Location {
+            source,
+            destinations: options.destinations.clone(),
+            name: name.clone(),
+            filter: Arc::clone(&options.filter),
+            symlinks: options.symlinks,
+            naming: options.naming,
+            fs_timeout: options.fs_timeout,
+        };
+        result.push(path_source(
+            location,
+            &stem,
+            has_trailing_separator(source_path),
+            as_link,
+            #[cfg(feature = "non-existing-option")]
+            register_nonexistent,
+        )?);
+    }
 
-        let stem = source
-            .file_stem()
-            .ok_or_else(|| CliError::SourceNoFileStem(source_path.clone()))?;
+    skip_covered_files(&mut result);
 
-        let watch_source = if source.is_dir() {
-            Source::Folder(Location {
-                source: source.clone(),
-                destination: target_base.join(stem),
-            })
-        } else {
-            if !source.exists() {
-                #[cfg(feature = "non-existing-option")]
-                if register_nonexistent {
-                    log::warn!("Path doesn't exist. Assuming it's a file: {source:?}");
-                } else {
-                    return Err(CliError::SourceNonexistent(source));
-                }
-                #[cfg(not(feature = "non-existing-option"))]
-                {
-                    log::warn!("Path doesn't exist. Assuming it's a file: {source:?}");
-                }
-            } else if !source.is_file() {
-                return Err(CliError::SourceUnsupported(source));
-            } else { /* create a value */
-            }
+    Ok(result)
+}
+
+/// Check that an aliased source shares no destination with an earlier source
+/// of the same alias, their backups would mix.
+fn check_duplicate_alias<'a, I>(
+    known: I,
+    name: Option<&str>,
+    options: &SourceOptions,
+) -> Result<(), CliError>
+where
+    I: IntoIterator<Item = &'a (PathBuf, Option<String>, SourceOptions)>,
+{
+    let Some(alias) = name else {
+        return Ok(());
+    };
+
+    let is_duplicate = known.into_iter().any(|(_, known_name, known_options)| {
+        known_name.as_deref() == Some(alias)
+            && known_options
+                .destinations
+                .iter()
+                .any(|destination| options.destinations.contains(destination))
+    });
+    if is_duplicate {
+        return Err(CliError::DuplicateAlias(alias.to_owned()));
+    }
+
+    Ok(())
+}
+
+/// Source of a glob pattern, its matches of an alias are kept in a folder of
+/// the alias.
+fn glob_source(
+    pattern: SourcePattern,
+    name: Option<&str>,
+    options: &SourceOptions,
+    known: &[Source],
+) -> Result<Source, CliError> {
+    let source = pattern.root().to_path_buf();
+    if known.iter().any(|other| other.source() == &source) {
+        return Err(CliError::GlobSharingFolder(source));
+    }
+    if !pattern.has_matches() {
+        log::warn!("Nothing matches {pattern} yet, waiting for it");
+    }
+
+    Ok(Source::Glob(Location {
+        source,
+        destinations: name.map_or_else(
+            || options.destinations.clone(),
+            |alias| {
+                options
+                    .destinations
+                    .iter()
+                    .map(|destination| destination.join(alias))
+                    .collect()
+            },
+        ),
+        name: name.map(str::to_owned),
+        filter: Arc::new(options.filter.with_pattern(pattern)),
+        symlinks: options.symlinks,
+        naming: options.naming,
+        fs_timeout: options.fs_timeout,
+    }))
+}
+
+/// Source path after the symlink policy, `None` for a skipped link.
+fn apply_symlink_policy(
+    path: PathBuf,
+    symlinks: SymlinkPolicy,
+    is_symlink: bool,
+) -> Result<Option<PathBuf>, CliError> {
+    match symlinks {
+        _ if !is_symlink => Ok(Some(path)),
+        SymlinkPolicy::Skip => {
+            log::warn!("Skipping symlink {path:?}");
+            Ok(None)
+        }
+        SymlinkPolicy::CopyLink => Ok(Some(path)),
+        SymlinkPolicy::Follow => {
+            let target =
+                canonicalize(&path).map_err(|error| CliError::SourceBrokenLink {
+                    filename: path.clone(),
+                    error,
+                })?;
+            log::info!("Following symlink {path:?} -> {target:?}");
+            Ok(Some(target))
+        }
+    }
+}
 
-            Source::File(Location {
+/// Folder or file source of a path, a missing path written as a folder is a
+/// folder source.
+fn path_source(
+    location: Location,
+    stem: &OsStr,
+    written_as_folder: bool,
+    as_link: bool,
+    #[cfg(feature = "non-existing-option")] register_nonexistent: bool,
+) -> Result<Source, CliError> {
+    let source = &location.source;
+
+    if !as_link && (source.is_dir() || (!source.exists() && written_as_folder)) {
+        if !source.exists() {
+            check_nonexistent(
                 source,
-                destination: target_base,
-            })
-        };
+                #[cfg(feature = "non-existing-option")]
+                register_nonexistent,
+            )?;
+            log::warn!("Folder doesn't exist yet: {source:?}");
+        }
+
+        // without trailing separator
+        let folder = source.components().collect();
+        let destinations = location
+            .destinations
+            .iter()
+            .map(|destination| destination.join(stem))
+            .collect();
+        return Ok(Source::Folder(Location {
+            source: folder,
+            destinations,
+            ..location
+        }));
+    }
 
-        result.push(watch_source);
+    if !source.exists() {
+        check_nonexistent(
+            source,
+            #[cfg(feature = "non-existing-option")]
+            register_nonexistent,
+        )?;
+        log::warn!(
+            "Path doesn't exist. Assuming it's a file until a folder appears: {source:?}"
+        );
+    } else if !as_link && !source.is_file() {
+        return Err(CliError::SourceUnsupported(location.source));
+    } else { /* create a value */
     }
 
-    let folder_locations = result
+    Ok(Source::File(location))
+}
+
+/// Skip file sources in folders of folder sources, they are already covered.
+fn skip_covered_files(sources: &mut Vec<Source>) {
+    let folder_locations = sources
         .iter()
         .filter_map(|known| {
             if let Source::Folder(location) = known {
@@ -124,7 +1241,7 @@ fn convert_sources(
         })
         .collect::<Vec<_>>();
 
-    result.retain(|source| {
+    sources.retain(|source| {
         let Source::File(location) = source else {
             return true;
         };
@@ -142,6 +1259,31 @@ fn convert_sources(
             true
         }
     });
+}
 
-    Ok(result)
+/// Check if nonexistent source is allowed.
+#[cfg_attr(
+    not(feature = "non-existing-option"),
+    allow(
+        unused_variables,
+        clippy::unnecessary_wraps,
+        clippy::missing_const_for_fn,
+        reason = "Always allowed without the feature"
+    )
+)]
+fn check_nonexistent(
+    source: &Path,
+    #[cfg(feature = "non-existing-option")] register_nonexistent: bool,
+) -> Result<(), CliError> {
+    #[cfg(feature = "non-existing-option")]
+    if !register_nonexistent {
+        return Err(CliError::SourceNonexistent(source.to_path_buf()));
+    }
+
+    Ok(())
+}
+
+/// Check if the path is written as a folder, e.g. `saves/`.
+fn has_trailing_separator(path: &Path) -> bool {
+    path.as_os_str().to_string_lossy().ends_with(is_separator)
 }
@@ -0,0 +1,52 @@
+use super::CommandError;
+use crate::args::CtlOptions;
+use crate::control::{Request, Response, send};
+use crate::time_utils::format_time;
+
+/// Send a request to a running watcher and print its response.
+pub fn ctl(options: &CtlOptions) -> Result<(), CommandError> {
+    let response = send(&options.socket, &options.request)?;
+    if !response.ok {
+        return Err(CommandError::Rejected(
+            response
+                .error
+                .unwrap_or_else(|| "Request failed".to_owned()),
+        ));
+    }
+
+    print_response(&options.request, &response);
+    Ok(())
+}
+
+#[allow(clippy::print_stdout, reason = "Command output")]
+fn print_response(request: &Request, response: &Response) {
+    for status in &response.sources {
+        let last_time = if status.last_time == 0 {
+            "-".to_owned()
+        } else {
+            format_time(status.last_time)
+        };
+        let paused = if status.paused { "\tpaused" } else { "" };
+        println!(
+            "{:x}\t{last_time}\t{}{paused}",
+            status.id,
+            status.path.display()
+        );
+    }
+
+    for record in &response.outcomes {
+        println!("{}\t{}", record.outcome, record.path.display());
+    }
+
+    if response.sources.is_empty() && response.outcomes.is_empty() {
+        match request {
+            Request::Status => println!("No registered files"),
+            Request::Add { .. }
+            | Request::Remove { .. }
+            | Request::Pause { .. }
+            | Request::Resume { .. }
+            | Request::Backup { .. }
+            | Request::Shutdown => println!("OK"),
+        }
+    }
+}
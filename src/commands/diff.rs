@@ -0,0 +1,251 @@
+use alloc::collections::BTreeMap;
+use std::fs::read;
+use std::path::Path;
+
+use similar::TextDiff;
+
+use super::{CommandError, source_backups};
+use crate::args::DiffOptions;
+
+/// Flat map of key paths to their values.
+type Keys = BTreeMap<String, String>;
+
+/// Print difference between two versions of a source.
+///
+/// Text files get a unified diff, JSON, TOML and INI files a key-level diff,
+/// other files a binary summary.
+pub fn diff(options: &DiffOptions) -> Result<(), CommandError> {
+    let backups = source_backups(&options.source)?;
+    let Some(source_info) = backups.source_info else {
+        return Err(CommandError::IdForFolder {
+            source: options.source.path.clone(),
+        });
+    };
+
+    let path_a = source_info.backup_path(&backups.folder, options.id_a);
+    let path_b = source_info.backup_path(&backups.folder, options.id_b);
+    let content_a = read_version(&path_a)?;
+    let content_b = read_version(&path_b)?;
+
+    let text = as_text(&content_a).zip(as_text(&content_b));
+
+    #[allow(clippy::print_stdout, reason = "Command output")]
+    if let Some((text_a, text_b)) = text {
+        let extension = source_info
+            .extension
+            .as_ref()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        let keys = extension.as_deref().and_then(|lowercase| {
+            parse_keys(lowercase, text_a).zip(parse_keys(lowercase, text_b))
+        });
+
+        if let Some((keys_a, keys_b)) = keys {
+            print!("{}", key_diff(&keys_a, &keys_b));
+        } else {
+            print!(
+                "{}",
+                TextDiff::from_lines(text_a, text_b)
+                    .unified_diff()
+                    .header(&path_a.to_string_lossy(), &path_b.to_string_lossy())
+            );
+        }
+    } else {
+        print!("{}", binary_summary(&content_a, &content_b));
+    }
+
+    Ok(())
+}
+
+/// Read a backup version.
+fn read_version(path: &Path) -> Result<Vec<u8>, CommandError> {
+    read(path).map_err(|error| CommandError::Read {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Content as text if it's UTF-8 without NUL bytes.
+fn as_text(content: &[u8]) -> Option<&str> {
+    if content.contains(&0) {
+        return None;
+    }
+
+    str::from_utf8(content).ok()
+}
+
+/// Parse structured text into flat keys by file extension.
+fn parse_keys(extension: &str, text: &str) -> Option<Keys> {
+    let mut keys = Keys::new();
+
+    match extension {
+        "json" => match serde_json::from_str::<serde_json::Value>(text) {
+            Ok(value) => flatten_json(&mut keys, String::new(), &value),
+            Err(error) => {
+                log::warn!("Not a valid JSON, showing a text diff: {error}");
+                return None;
+            }
+        },
+        "toml" => match toml::from_str::<toml::Table>(text) {
+            Ok(table) => {
+                for (key, value) in &table {
+                    flatten_toml(&mut keys, key.clone(), value);
+                }
+            }
+            Err(error) => {
+                log::warn!("Not a valid TOML, showing a text diff: {error}");
+                return None;
+            }
+        },
+        "ini" => parse_ini(&mut keys, text),
+        _ => return None,
+    }
+
+    Some(keys)
+}
+
+/// Join a parent key path and a key.
+fn join_key(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{parent}.{key}")
+    }
+}
+
+/// Flatten JSON value, array items are keyed by index.
+fn flatten_json(keys: &mut Keys, path: String, value: &serde_json::Value) {
+    match value {
+        serde_json::Value::Object(object) => {
+            for (key, child) in object {
+                flatten_json(keys, join_key(&path, key), child);
+            }
+        }
+        serde_json::Value::Array(array) => {
+            for (index, item) in array.iter().enumerate() {
+                flatten_json(keys, format!("{path}[{index}]"), item);
+            }
+        }
+        serde_json::Value::Null
+        | serde_json::Value::Bool(_)
+        | serde_json::Value::Number(_)
+        | serde_json::Value::String(_) => {
+            keys.insert(path, value.to_string());
+        }
+    }
+}
+
+/// Flatten TOML value, array items are keyed by index.
+fn flatten_toml(keys: &mut Keys, path: String, value: &toml::Value) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, child) in table {
+                flatten_toml(keys, join_key(&path, key), child);
+            }
+        }
+        toml::Value::Array(array) => {
+            for (index, item) in array.iter().enumerate() {
+                flatten_toml(keys, format!("{path}[{index}]"), item);
+            }
+        }
+        toml::Value::String(_)
+        | toml::Value::Integer(_)
+        | toml::Value::Float(_)
+        | toml::Value::Boolean(_)
+        | toml::Value::Datetime(_) => {
+            keys.insert(path, value.to_string());
+        }
+    }
+}
+
+/// Parse INI `[section]` and `key = value` lines, comments start with `;` or `#`.
+fn parse_ini(keys: &mut Keys, text: &str) {
+    let mut section = String::new();
+
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|header| header.strip_suffix(']'))
+        {
+            name.trim().clone_into(&mut section);
+        } else if let Some((key, value)) = line.split_once('=') {
+            keys.insert(join_key(&section, key.trim()), value.trim().to_owned());
+        } else {
+            keys.insert(join_key(&section, line), String::new());
+        }
+    }
+}
+
+/// Describe added, removed and changed keys.
+fn key_diff(keys_a: &Keys, keys_b: &Keys) -> String {
+    let mut lines = Vec::new();
+
+    for (key, value_a) in keys_a {
+        match keys_b.get(key) {
+            None => lines.push(format!("- {key} = {value_a}\n")),
+            Some(value_b) if value_b != value_a => {
+                lines.push(format!("~ {key} = {value_a} -> {value_b}\n"));
+            }
+            Some(_) => {}
+        }
+    }
+
+    for (key, value_b) in keys_b {
+        if !keys_a.contains_key(key) {
+            lines.push(format!("+ {key} = {value_b}\n"));
+        }
+    }
+
+    if lines.is_empty() {
+        return "No changed keys\n".to_owned();
+    }
+
+    lines.concat()
+}
+
+/// Describe size delta, changed byte ranges and the first differing offset.
+fn binary_summary(content_a: &[u8], content_b: &[u8]) -> String {
+    let mut ranges: usize = 0;
+    let mut first_offset = None;
+    let mut in_range = false;
+
+    for (offset, (byte_a, byte_b)) in content_a.iter().zip(content_b).enumerate() {
+        if byte_a == byte_b {
+            in_range = false;
+            continue;
+        }
+
+        first_offset.get_or_insert(offset);
+        if !in_range {
+            ranges += 1;
+            in_range = true;
+        }
+    }
+
+    // A longer version differs by its tail.
+    let common = content_a.len().min(content_b.len());
+    if content_a.len() != content_b.len() {
+        first_offset.get_or_insert(common);
+        if !in_range {
+            ranges += 1;
+        }
+    }
+
+    let delta = i128::try_from(content_b.len()).unwrap_or(i128::MAX)
+        - i128::try_from(content_a.len()).unwrap_or(i128::MAX);
+
+    let first_difference = first_offset.map_or_else(
+        || "Contents are identical".to_owned(),
+        |offset| format!("First difference at offset {offset} (0x{offset:x})"),
+    );
+
+    format!(
+        "Binary files: {} -> {} bytes ({delta:+})\nChanged ranges: {ranges}\n{first_difference}\n",
+        content_a.len(),
+        content_b.len()
+    )
+}
@@ -0,0 +1,627 @@
+use alloc::collections::BTreeSet;
+use std::fs::{
+    File, OpenOptions, create_dir, read_to_string, remove_dir, remove_file, rename,
+};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write as _};
+use std::path::{Path, PathBuf};
+
+use enum_display::EnumDisplay;
+
+use crate::args::{Location, Source, SymlinkPolicy, WatchArgs, WatchMode};
+use crate::dry_run;
+use crate::utils::ParentPath as _;
+
+/// Name of probe files written to destinations, removed after the check.
+const PROBE: &str = ".little-becky.doctor";
+
+/// Destinations with less room than this many versions of sources are warned.
+const SPACE_VERSIONS: u64 = 10;
+
+/// Result of a check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumDisplay)]
+#[enum_display(case = "Lower")]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// A checked property of a source or a destination.
+struct Check {
+    status: Status,
+    name: &'static str,
+    path: PathBuf,
+    detail: String,
+}
+
+impl Check {
+    fn new(status: Status, name: &'static str, path: &Path, detail: String) -> Self {
+        Self {
+            status,
+            name,
+            path: path.to_path_buf(),
+            detail,
+        }
+    }
+}
+
+/// Check sources and destinations of a normal run and print the results.
+///
+/// Return false if any check failed.
+pub fn doctor(args: &WatchArgs) -> bool {
+    let mut checks = vec![];
+
+    for source in &args.sources {
+        checks.push(check_read(source));
+    }
+
+    // Other checks of an unavailable destination would fail the same way.
+    for destination in &args.destinations {
+        if let Some(check) = check_available(destination) {
+            checks.push(check);
+            continue;
+        }
+        checks.push(check_write(destination));
+        checks.push(check_space(destination, &args.sources));
+    }
+
+    checks.extend(check_watch_limits(args));
+
+    for source in &args.sources {
+        let location = source.location();
+        for destination in &location.destinations {
+            checks.push(check_loop(source, destination));
+            if destination.is_dir() {
+                checks.extend(check_filesystem(location, destination));
+            }
+        }
+    }
+
+    checks.extend(
+        args.destinations
+            .iter()
+            .filter(|destination| destination.is_dir())
+            .filter_map(|destination| check_reflink(destination, &args.sources)),
+    );
+
+    print_checks(&checks);
+
+    checks.iter().all(|check| check.status != Status::Fail)
+}
+
+/// Print checks as a table followed by totals.
+#[allow(clippy::print_stdout, reason = "Command output")]
+fn print_checks(checks: &[Check]) {
+    let name_width = checks
+        .iter()
+        .map(|check| check.name.len())
+        .max()
+        .unwrap_or_default();
+    let paths = checks
+        .iter()
+        .map(|check| format!("{:?}", check.path))
+        .collect::<Vec<_>>();
+    let path_width = paths.iter().map(String::len).max().unwrap_or_default();
+
+    for (check, path) in checks.iter().zip(&paths) {
+        println!(
+            "{:<4}  {:<name_width$}  {path:<path_width$}  {}",
+            check.status.to_string(),
+            check.name,
+            check.detail
+        );
+    }
+
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    println!(
+        "{} passed, {} warnings, {} failed",
+        count(Status::Pass),
+        count(Status::Warn),
+        count(Status::Fail)
+    );
+}
+
+/// Check that source files are readable.
+fn check_read(source: &Source) -> Check {
+    let location = source.location();
+    let check = |status, detail| Check::new(status, "read", &location.source, detail);
+
+    let files = match source {
+        Source::File(_) if !exists(&location.source) => {
+            return check(Status::Warn, "doesn't exist yet".to_owned());
+        }
+        Source::File(_) => vec![location.source.clone()],
+        Source::Folder(_) => match folder_files(location) {
+            Ok(files) => files,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return check(Status::Warn, "folder doesn't exist yet".to_owned());
+            }
+            Err(error) => return check(Status::Fail, error.to_string()),
+        },
+        Source::Glob(_) => {
+            let mut files = vec![];
+            for folder in location.glob_folders(&location.source) {
+                match folder_files(&folder) {
+                    Ok(found) => files.extend(found),
+                    Err(error) => {
+                        return check(
+                            Status::Fail,
+                            format!("{:?}: {error}", folder.source),
+                        );
+                    }
+                }
+            }
+            if files.is_empty() {
+                return check(Status::Warn, "nothing matches yet".to_owned());
+            }
+            files
+        }
+    };
+
+    let unreadable = files
+        .iter()
+        .filter_map(|file| {
+            read_probe(file, location.symlinks)
+                .err()
+                .map(|error| (file, error))
+        })
+        .collect::<Vec<_>>();
+
+    match unreadable.first() {
+        None => check(
+            Status::Pass,
+            format!("{} readable", plural(files.len(), "file")),
+        ),
+        Some((file, error)) => check(
+            Status::Fail,
+            format!(
+                "{} of {} unreadable, {file:?}: {error}",
+                unreadable.len(),
+                plural(files.len(), "file")
+            ),
+        ),
+    }
+}
+
+/// Files of a folder source accepted by its filter.
+fn folder_files(location: &Location) -> IoResult<Vec<PathBuf>> {
+    let mut files = location
+        .source
+        .read_dir()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            (path.is_file() || path.is_symlink()) && location.filter.accepts(path)
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// Open a file for reading, links copied as links are read as links.
+fn read_probe(file: &Path, symlinks: SymlinkPolicy) -> IoResult<()> {
+    if file.is_symlink() && symlinks == SymlinkPolicy::CopyLink {
+        file.read_link().map(|_| ())
+    } else {
+        File::open(file).map(|_| ())
+    }
+}
+
+/// Check that a destination is an existing folder, a normal run skips it or
+/// fails otherwise.
+///
+/// Return `None` if it's available.
+fn check_available(destination: &Path) -> Option<Check> {
+    let check = |detail: &str| {
+        Some(Check::new(
+            Status::Fail,
+            "destination",
+            destination,
+            detail.to_owned(),
+        ))
+    };
+
+    if destination.is_dir() {
+        None
+    } else if exists(destination) {
+        check("not a folder")
+    } else {
+        check("doesn't exist, e.g. an unmounted drive")
+    }
+}
+
+/// Check that files and folders can be created, written and renamed in a
+/// destination.
+fn check_write(destination: &Path) -> Check {
+    let check = |status, detail| Check::new(status, "write", destination, detail);
+
+    if dry_run::enabled() {
+        return check(Status::Warn, "not checked in dry run".to_owned());
+    }
+
+    match write_probe(destination) {
+        Ok(()) => check(Status::Pass, "create, write and rename work".to_owned()),
+        Err((step, error)) => check(Status::Fail, format!("unable to {step}: {error}")),
+    }
+}
+
+/// Create, write, rename and remove probe files and a probe folder.
+///
+/// Return the failed step with its error.
+#[allow(
+    clippy::create_dir,
+    clippy::let_underscore_must_use,
+    reason = "A single probe folder is checked, probes may be missing on cleanup"
+)]
+fn write_probe(destination: &Path) -> Result<(), (&'static str, IoError)> {
+    let probe = destination.join(PROBE);
+    let renamed = destination.join(format!("{PROBE}.renamed"));
+    let folder = destination.join(format!("{PROBE}.d"));
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe)
+        .map_err(|error| ("create a file", error))?;
+    let written = file
+        .write_all(PROBE.as_bytes())
+        .and_then(|()| file.sync_all());
+    drop(file);
+
+    let result = written
+        .map_err(|error| ("write a file", error))
+        .and_then(|()| {
+            rename(&probe, &renamed).map_err(|error| ("rename a file", error))
+        })
+        .and_then(|()| create_dir(&folder).map_err(|error| ("create a folder", error)));
+
+    _ = remove_file(&probe);
+    _ = remove_file(&renamed);
+    _ = remove_dir(&folder);
+
+    result
+}
+
+/// Check free space of a destination against current size of its sources.
+fn check_space(destination: &Path, sources: &[Source]) -> Check {
+    let check = |status, detail| Check::new(status, "free space", destination, detail);
+
+    let available = match available_space(destination) {
+        Ok(Some(available)) => available,
+        Ok(None) => {
+            return check(Status::Pass, "not checked on this platform".to_owned());
+        }
+        Err(error) => return check(Status::Warn, error.to_string()),
+    };
+
+    let needed = sources
+        .iter()
+        .filter(|source| {
+            source
+                .location()
+                .destinations
+                .iter()
+                .any(|known| known.starts_with(destination))
+        })
+        .map(sources_size)
+        .sum::<u64>();
+
+    let detail = format!(
+        "{} free, one version of sources takes {}",
+        format_size(available),
+        format_size(needed)
+    );
+    if available < needed {
+        check(Status::Fail, detail)
+    } else if available < needed.saturating_mul(SPACE_VERSIONS) {
+        check(
+            Status::Warn,
+            format!("{detail}, less than {SPACE_VERSIONS} versions fit"),
+        )
+    } else {
+        check(Status::Pass, detail)
+    }
+}
+
+/// Size of existing files of a source.
+fn sources_size(source: &Source) -> u64 {
+    let location = source.location();
+    let files = match source {
+        Source::File(_) => vec![location.source.clone()],
+        Source::Folder(_) => folder_files(location).unwrap_or_default(),
+        Source::Glob(_) => location
+            .glob_folders(&location.source)
+            .iter()
+            .flat_map(|folder| folder_files(folder).unwrap_or_default())
+            .collect(),
+    };
+
+    files
+        .iter()
+        .filter_map(|file| file.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Check inotify limits against debouncers a run creates, one per watched folder.
+fn check_watch_limits(args: &WatchArgs) -> Vec<Check> {
+    if args.watch.watch_mode == WatchMode::Poll || !cfg!(target_os = "linux") {
+        return vec![];
+    }
+
+    let mut folders = BTreeSet::new();
+    for source in &args.sources {
+        let location = source.location();
+        match source {
+            Source::File(_) => {
+                folders.insert(nearest_existing(location.source.parent_path()));
+            }
+            Source::Folder(_) => {
+                folders.insert(nearest_existing(&location.source));
+            }
+            Source::Glob(_) => {
+                folders.insert(nearest_existing(&location.source));
+                folders.extend(
+                    location
+                        .glob_folders(&location.source)
+                        .into_iter()
+                        .map(|folder| folder.source),
+                );
+            }
+        }
+    }
+    // The configuration file is watched for reloads.
+    let needed = folders.len() + usize::from(args.config_path.is_some());
+
+    // Native watches fall back to polling in auto mode.
+    let exceeded = if args.watch.watch_mode == WatchMode::Auto {
+        Status::Warn
+    } else {
+        Status::Fail
+    };
+
+    [
+        ("inotify instances", "max_user_instances"),
+        ("inotify watches", "max_user_watches"),
+    ]
+    .into_iter()
+    .map(|(name, setting)| {
+        let path = Path::new("/proc/sys/fs/inotify").join(setting);
+        let check = |status, detail| Check::new(status, name, &path, detail);
+
+        let limit = match read_to_string(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|value| {
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|error| error.to_string())
+            }) {
+            Ok(limit) => limit,
+            Err(error) => return check(Status::Warn, error),
+        };
+
+        let detail = format!("{needed} needed, limit is {limit}");
+        if needed > limit {
+            check(exceeded, detail)
+        } else if needed.saturating_mul(2) > limit {
+            check(Status::Warn, format!("{detail}, other programs share it"))
+        } else {
+            check(Status::Pass, detail)
+        }
+    })
+    .collect()
+}
+
+/// Check that backups are not written where the source is watched.
+fn check_loop(source: &Source, destination: &Path) -> Check {
+    let location = source.location();
+    let check = |status, detail: &str| {
+        Check::new(status, "loop", &location.source, detail.to_owned())
+    };
+
+    let inside_watched = match source {
+        Source::File(_) => destination == location.source.parent_path(),
+        Source::Folder(_) => destination == location.source,
+        Source::Glob(_) => location
+            .filter
+            .pattern()
+            .is_some_and(|pattern| pattern.may_contain(destination)),
+    };
+
+    if inside_watched {
+        match source {
+            Source::File(_) => check(
+                Status::Warn,
+                "backups are written into the folder of the source",
+            ),
+            Source::Folder(_) | Source::Glob(_) => {
+                check(Status::Fail, "backups are written into the source")
+            }
+        }
+    } else if destination.starts_with(&location.source) {
+        check(Status::Warn, "destination is inside the source")
+    } else if location.source.starts_with(destination) {
+        check(Status::Warn, "source is inside the destination")
+    } else {
+        check(Status::Pass, "destination is outside the source")
+    }
+}
+
+/// Check if a source and its destination share a filesystem.
+#[cfg(unix)]
+fn check_filesystem(location: &Location, destination: &Path) -> Option<Check> {
+    let source_device = device(&nearest_existing(&location.source))?;
+    let destination_device = device(&nearest_existing(destination))?;
+
+    Some(if source_device == destination_device {
+        Check::new(
+            Status::Warn,
+            "filesystem",
+            &location.source,
+            format!("same as {destination:?}, a disk failure loses both"),
+        )
+    } else {
+        Check::new(
+            Status::Pass,
+            "filesystem",
+            &location.source,
+            format!("differs from {destination:?}"),
+        )
+    })
+}
+
+#[cfg(not(unix))]
+fn check_filesystem(_location: &Location, _destination: &Path) -> Option<Check> {
+    None
+}
+
+/// Check if copies into a destination share blocks with sources on its
+/// filesystem.
+#[cfg(target_os = "linux")]
+fn check_reflink(destination: &Path, sources: &[Source]) -> Option<Check> {
+    let destination_device = device(destination)?;
+    let shared = sources.iter().any(|source| {
+        device(&nearest_existing(&source.location().source)) == Some(destination_device)
+    });
+    if !shared || dry_run::enabled() {
+        return None;
+    }
+
+    let check = |detail: &str| {
+        Check::new(Status::Pass, "reflink", destination, detail.to_owned())
+    };
+
+    Some(match reflink_probe(destination) {
+        Ok(()) => check("supported, copies share blocks with sources"),
+        Err(error) => {
+            log::debug!("Reflink is unavailable in {destination:?}: {error}");
+            check("not supported, copies take full space")
+        }
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn check_reflink(_destination: &Path, _sources: &[Source]) -> Option<Check> {
+    None
+}
+
+/// Clone a probe file in a destination.
+#[cfg(target_os = "linux")]
+#[allow(
+    clippy::let_underscore_must_use,
+    reason = "Probes may be missing on cleanup"
+)]
+fn reflink_probe(destination: &Path) -> IoResult<()> {
+    use std::os::fd::AsRawFd as _;
+
+    let probe = destination.join(PROBE);
+    let clone = destination.join(format!("{PROBE}.clone"));
+
+    let result = (|| {
+        let mut writer = File::create(&probe)?;
+        writer.write_all(PROBE.as_bytes())?;
+        writer.sync_all()?;
+        let source = File::open(&probe)?;
+        let target = File::create(&clone)?;
+
+        // SAFETY: both descriptors are open for the duration of the call.
+        let result = unsafe {
+            libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd())
+        };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(IoError::last_os_error())
+        }
+    })();
+
+    _ = remove_file(&probe);
+    _ = remove_file(&clone);
+
+    result
+}
+
+/// Space available to the user in the filesystem of a folder.
+///
+/// Return `None` on platforms where it's not checked.
+#[cfg(unix)]
+fn available_space(folder: &Path) -> IoResult<Option<u64>> {
+    use alloc::ffi::CString;
+    use core::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt as _;
+
+    let path = CString::new(folder.as_os_str().as_bytes())
+        .map_err(|error| IoError::new(ErrorKind::InvalidInput, error))?;
+    let mut buffer = MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: the path is a valid C string and the buffer is large enough.
+    if unsafe { libc::statvfs(path.as_ptr(), buffer.as_mut_ptr()) } != 0 {
+        return Err(IoError::last_os_error());
+    }
+    // SAFETY: the buffer is filled by a successful call.
+    let stat = unsafe { buffer.assume_init() };
+
+    #[allow(clippy::useless_conversion, reason = "Field types differ by platform")]
+    Ok(Some(
+        u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)),
+    ))
+}
+
+#[cfg(not(unix))]
+fn available_space(_folder: &Path) -> IoResult<Option<u64>> {
+    Ok(None)
+}
+
+/// Filesystem device of a path.
+#[cfg(unix)]
+fn device(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt as _;
+
+    path.metadata().ok().map(|metadata| metadata.dev())
+}
+
+/// Nearest existing path, the path itself included.
+fn nearest_existing(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+/// Check if a file or a link exists.
+fn exists(path: &Path) -> bool {
+    path.exists() || path.is_symlink()
+}
+
+/// Count with a noun, e.g. `1 file` or `2 files`.
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+/// Size with a binary unit, e.g. `1.5 GiB`.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::float_arithmetic,
+    reason = "Approximate size is printed"
+)]
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    match UNITS.get(unit) {
+        Some(&"B") | None => format!("{bytes} B"),
+        Some(name) => format!("{value:.1} {name}"),
+    }
+}
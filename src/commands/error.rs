@@ -0,0 +1,147 @@
+use core::error::Error;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::Error as IoError;
+use std::path::PathBuf;
+
+use crate::control::ControlError;
+use crate::lock::LockError;
+use crate::presets::PresetError;
+use crate::state::StateInitializeError;
+
+/// Error of commands working with existing backups.
+#[derive(Debug)]
+pub enum CommandError {
+    /// Unable to read backups.
+    State(StateInitializeError),
+
+    /// No version matches the selection.
+    VersionNotFound { source: PathBuf },
+
+    /// The source was deleted at the selected time.
+    VersionDeleted { source: PathBuf },
+
+    /// Backup id selects a version of a single file.
+    IdForFolder { source: PathBuf },
+
+    /// Command works with a file source only.
+    NotFile { source: PathBuf },
+
+    /// Destination is locked or the lock is unavailable.
+    Lock(LockError),
+
+    /// Target name is taken by a file not being renamed.
+    NameTaken { path: PathBuf },
+
+    /// Invalid regex built from a source name.
+    InvalidRegex(regex::Error),
+
+    /// Unable to read or write a reindex plan.
+    Plan {
+        path: PathBuf,
+        error: serde_json::Error,
+    },
+
+    /// Unable to talk to a running watcher.
+    Control(ControlError),
+
+    /// A running watcher refused the request.
+    Rejected(String),
+
+    /// Unable to read presets.
+    Preset(PresetError),
+
+    /// Unable to read a file.
+    Read { path: PathBuf, error: IoError },
+
+    /// Unable to write a file.
+    Write { path: PathBuf, error: IoError },
+
+    /// Unable to write command output.
+    Output(IoError),
+}
+
+impl From<StateInitializeError> for CommandError {
+    fn from(error: StateInitializeError) -> Self {
+        Self::State(error)
+    }
+}
+
+impl From<LockError> for CommandError {
+    fn from(error: LockError) -> Self {
+        Self::Lock(error)
+    }
+}
+
+impl From<ControlError> for CommandError {
+    fn from(error: ControlError) -> Self {
+        Self::Control(error)
+    }
+}
+
+impl From<PresetError> for CommandError {
+    fn from(error: PresetError) -> Self {
+        Self::Preset(error)
+    }
+}
+
+impl Error for CommandError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::State(error) => Some(error),
+            Self::Lock(error) => Some(error),
+            Self::InvalidRegex(error) => Some(error),
+            Self::Plan { error, .. } => Some(error),
+            Self::Control(error) => Some(error),
+            Self::Preset(error) => Some(error),
+            Self::Read { error, .. }
+            | Self::Write { error, .. }
+            | Self::Output(error) => Some(error),
+            Self::VersionNotFound { .. }
+            | Self::VersionDeleted { .. }
+            | Self::IdForFolder { .. }
+            | Self::NotFile { .. }
+            | Self::NameTaken { .. }
+            | Self::Rejected(_) => None,
+        }
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::State(error) => write!(f, "{error}"),
+            Self::VersionNotFound { source } => {
+                write!(f, "No backed up version matches for {source:?}")
+            }
+            Self::VersionDeleted { source } => {
+                write!(f, "Source {source:?} was deleted at the selected time")
+            }
+            Self::IdForFolder { source } => {
+                write!(f, "Backup id selects a single file, {source:?} is a folder")
+            }
+            Self::NotFile { source } => {
+                write!(f, "Expected a file source, {source:?} is a folder")
+            }
+            Self::Lock(error) => write!(f, "{error}"),
+            Self::InvalidRegex(error) => {
+                write!(f, "Invalid regular expression: {error}")
+            }
+            Self::NameTaken { path } => {
+                write!(f, "Target name is taken by another file: {path:?}")
+            }
+            Self::Plan { path, error } => {
+                write!(f, "Invalid reindex plan {path:?}: {error}")
+            }
+            Self::Control(error) => write!(f, "{error}"),
+            Self::Rejected(error) => write!(f, "{error}"),
+            Self::Preset(error) => write!(f, "{error}"),
+            Self::Read { path, error } => {
+                write!(f, "Unable to read {path:?}: {error}")
+            }
+            Self::Write { path, error } => {
+                write!(f, "Unable to write {path:?}: {error}")
+            }
+            Self::Output(error) => write!(f, "Unable to write output: {error}"),
+        }
+    }
+}
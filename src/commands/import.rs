@@ -0,0 +1,193 @@
+use std::path::{Path, PathBuf};
+
+use regex::{Regex, escape};
+
+use super::{CommandError, source_backups};
+use crate::args::{ImportOptions, ImportPattern};
+use crate::dry_run;
+use crate::lock::lock_destination;
+use crate::process;
+use crate::state::{SourceInfo, read_backups};
+use crate::time_utils;
+
+/// Foreign backup to import.
+struct Candidate {
+    path: PathBuf,
+    /// Embedded date or modification time.
+    time: u128,
+    /// Time is the embedded date, it's set as modification time of the backup.
+    dated: bool,
+    /// Embedded number, it orders files with the same time.
+    number: u64,
+}
+
+/// Import foreign backups of a source ordered by time after its existing versions.
+///
+/// Files keep their modification time, or take the date embedded in their name,
+/// a watcher started afterwards continues numbering after them.
+pub fn import(options: &ImportOptions) -> Result<(), CommandError> {
+    let _lock = lock_destination(&options.source.destination, false)?;
+    let backups = source_backups(&options.source)?;
+    let Some(source_info) = backups.source_info else {
+        return Err(CommandError::NotFile {
+            source: options.source.path.clone(),
+        });
+    };
+
+    // Foreign backups are named by the source file, not by its alias.
+    let file_info = SourceInfo::try_from(options.source.path.as_path())?;
+    let regex = pattern_regex(&options.pattern, &file_info)?;
+    let mut candidates = find_candidates(&options.folder, &regex)?;
+    if candidates.is_empty() {
+        log::warn!("No files to import in {:?}", options.folder);
+        return Ok(());
+    }
+    candidates.sort_by(|left, right| {
+        (left.time, left.number, &left.path).cmp(&(
+            right.time,
+            right.number,
+            &right.path,
+        ))
+    });
+
+    dry_run::create_dir_all(&backups.folder).map_err(|error| CommandError::Write {
+        path: backups.folder.clone(),
+        error,
+    })?;
+
+    let mut file_id = read_backups(&backups.folder)?
+        .iter()
+        .filter(|entry| entry.belongs_to(&source_info))
+        .map(|entry| entry.file_id + 1)
+        .max()
+        .unwrap_or(0);
+
+    for candidate in &candidates {
+        let mut target = source_info.backup_path(&backups.folder, file_id);
+        while target.symlink_metadata().is_ok() {
+            file_id += 1;
+            target = source_info.backup_path(&backups.folder, file_id);
+        }
+
+        let modified = candidate.dated.then_some(candidate.time);
+        process::import_file(&candidate.path, &target, options.move_files, modified)
+            .map_err(|error| CommandError::Write {
+                path: target.clone(),
+                error,
+            })?;
+        log::info!("Imported {:?} as {target:?}", candidate.path);
+        file_id += 1;
+    }
+
+    log::info!(
+        "Imported {} file(s) for {:?}",
+        candidates.len(),
+        options.source.path
+    );
+    Ok(())
+}
+
+/// Build a regex matching file names of foreign backups of the source.
+fn pattern_regex(
+    pattern: &ImportPattern,
+    source_info: &SourceInfo,
+) -> Result<Regex, CommandError> {
+    let stem = escape(&source_info.prefix.to_string_lossy());
+    let extension = source_info
+        .extension
+        .as_ref()
+        .map(|extension| format!(r"\.{}", escape(&extension.to_string_lossy())))
+        .unwrap_or_default();
+
+    let expression = match pattern {
+        ImportPattern::Regex(regex) => return Ok(regex.clone()),
+        ImportPattern::Numbered => format!(r"^{stem} ?\((?P<n>\d+)\){extension}$"),
+        ImportPattern::Bak => format!(r"^{stem}(?:{extension})?\.bak(?P<n>\d*)$"),
+        ImportPattern::Dated => format!(
+            r"^{stem}[-_ .](?P<date>\d{{4}}-\d{{2}}-\d{{2}}(?:[ _T]\d{{2}}[-:.]\d{{2}}(?:[-:.]\d{{2}})?)?){extension}$"
+        ),
+    };
+
+    Regex::new(&expression).map_err(CommandError::InvalidRegex)
+}
+
+/// Find files matching the regex in the folder.
+fn find_candidates(
+    folder: &Path,
+    regex: &Regex,
+) -> Result<Vec<Candidate>, CommandError> {
+    let read_dir = folder.read_dir().map_err(|error| CommandError::Read {
+        path: folder.to_path_buf(),
+        error,
+    })?;
+
+    let mut candidates = vec![];
+
+    for dir_entry in read_dir {
+        let path = dir_entry
+            .map_err(|error| CommandError::Read {
+                path: folder.to_path_buf(),
+                error,
+            })?
+            .path();
+
+        let Some(captures) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| regex.captures(name))
+        else {
+            continue;
+        };
+
+        if !path.is_file() {
+            log::debug!("Not a file, skipping {path:?}");
+            continue;
+        }
+
+        let embedded = captures.name("date").and_then(|date| {
+            let time = parse_embedded_date(date.as_str());
+            if time.is_none() {
+                log::debug!("Unrecognized date in {path:?}, using modification time");
+            }
+            time
+        });
+
+        let dated = embedded.is_some();
+        let time = match embedded {
+            Some(time) => time,
+            None => time_utils::fs_time(&path).map_err(|error| CommandError::Read {
+                path: path.clone(),
+                error,
+            })?,
+        };
+
+        let number = captures
+            .name("n")
+            .and_then(|number| number.as_str().parse().ok())
+            .unwrap_or(0);
+
+        candidates.push(Candidate {
+            path,
+            time,
+            dated,
+            number,
+        });
+    }
+
+    Ok(candidates)
+}
+
+/// Parse `YYYY-MM-DD[ HH-MM[-SS]]` date, time parts may be separated by `-`, `.`
+/// or `:`.
+fn parse_embedded_date(value: &str) -> Option<u128> {
+    let (date, rest) = value.split_at_checked(10)?;
+    let time = rest
+        .trim_start_matches([' ', '_', 'T'])
+        .replace(['-', '.'], ":");
+
+    if time.is_empty() {
+        time_utils::parse_time(date).ok()
+    } else {
+        time_utils::parse_time(&format!("{date} {time}")).ok()
+    }
+}
@@ -0,0 +1,149 @@
+use std::io::{Result as IoResult, Write as _, stdout};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use super::{
+    CommandError, SourceBackups, destination_folders, file_name, output_result,
+    source_backups,
+};
+use crate::args::ListOptions;
+use crate::state::{
+    BackupEntry, BackupKind, SourceInfo, StateInitializeError, read_backups,
+};
+use crate::time_utils;
+
+/// Version of a source as printed in JSON output.
+#[derive(Debug, Serialize)]
+struct ListRecord {
+    source: String,
+    id: u64,
+    id_hex: String,
+    kind: BackupKind,
+    time: u128,
+    time_local: String,
+    size: u64,
+    path: PathBuf,
+}
+
+/// Print version history of sources without starting any watcher.
+pub fn list(options: &ListOptions) -> Result<(), CommandError> {
+    let mut records = vec![];
+
+    for group in list_groups(options)? {
+        let mut entries = read_backups(&group.folder)?
+            .into_iter()
+            .filter(|entry| entry.kind != BackupKind::Collision)
+            .filter(|entry| {
+                group
+                    .source_info
+                    .as_ref()
+                    .is_none_or(|source_info| entry.belongs_to(source_info))
+            })
+            .filter(|entry| options.since.is_none_or(|since| entry.time >= since))
+            .filter(|entry| options.until.is_none_or(|until| entry.time <= until))
+            .collect::<Vec<_>>();
+
+        entries.sort_by(|left, right| {
+            let (left_info, right_info) = (&left.source_info, &right.source_info);
+            (&left_info.prefix, &left_info.extension, left.file_id).cmp(&(
+                &right_info.prefix,
+                &right_info.extension,
+                right.file_id,
+            ))
+        });
+
+        for entry in &entries {
+            records.push(to_record(
+                &group.destination,
+                &group.folder,
+                group.source_info.as_ref().unwrap_or(&entry.source_info),
+                entry,
+            ));
+        }
+    }
+
+    if options.json {
+        match serde_json::to_string_pretty(&records) {
+            Ok(json) => output_result(writeln!(stdout().lock(), "{json}")),
+            Err(error) => {
+                log::error!("Unable to serialize list: {error}");
+                Ok(())
+            }
+        }
+    } else {
+        output_result(print_records(&records))
+    }
+}
+
+/// Print records grouped by their source.
+fn print_records(records: &[ListRecord]) -> IoResult<()> {
+    let mut out = stdout().lock();
+    let mut last_source = None;
+    for record in records {
+        if last_source != Some(&record.source) {
+            writeln!(out, "{}:", record.source)?;
+            last_source = Some(&record.source);
+        }
+
+        let size = if record.kind == BackupKind::Tombstone {
+            "deleted".to_owned()
+        } else {
+            record.size.to_string()
+        };
+
+        writeln!(
+            out,
+            "{:>8} {:>10} {} {size:>10} {:?}",
+            record.id_hex, record.id, record.time_local, record.path
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Resolve folders and sources to read.
+///
+/// Without sources the destination and its immediate subfolders are listed.
+fn list_groups(
+    options: &ListOptions,
+) -> Result<Vec<SourceBackups>, StateInitializeError> {
+    let destination = &options.destination;
+
+    if options.sources.is_empty() {
+        return Ok(destination_folders(destination)?
+            .into_iter()
+            .map(|folder| SourceBackups {
+                destination: destination.clone(),
+                folder,
+                source_info: None,
+            })
+            .collect());
+    }
+
+    options.sources.iter().map(source_backups).collect()
+}
+
+/// Convert backup entry of the source into output record.
+fn to_record(
+    destination: &Path,
+    folder: &Path,
+    source_info: &SourceInfo,
+    entry: &BackupEntry,
+) -> ListRecord {
+    let source = folder
+        .strip_prefix(destination)
+        .unwrap_or(folder)
+        .join(file_name(source_info));
+
+    ListRecord {
+        source: source.to_string_lossy().into_owned(),
+        id: entry.file_id,
+        id_hex: format!("{:x}", entry.file_id),
+        kind: entry.kind,
+        time: entry.time,
+        time_local: time_utils::format_time(entry.time),
+        size: entry.size,
+        path: entry.path.clone(),
+    }
+}
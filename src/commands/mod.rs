@@ -0,0 +1,137 @@
+mod ctl;
+mod diff;
+mod doctor;
+mod error;
+mod import;
+mod list;
+mod presets;
+mod reindex;
+mod restore;
+
+use std::ffi::OsStr;
+use std::io::{ErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+
+pub use self::ctl::ctl;
+pub use self::diff::diff;
+pub use self::doctor::doctor;
+pub use self::error::CommandError;
+pub use self::import::import;
+pub use self::list::list;
+pub use self::presets::presets;
+pub use self::reindex::{has_unfinished_reindex, reindex};
+pub use self::restore::restore;
+
+use crate::args::SourceRef;
+use crate::state::{SourceInfo, StateInitializeError, read_backups};
+
+/// Destination folder to read and optional source to select backups of.
+struct SourceBackups {
+    /// Destination root the folder is in.
+    destination: PathBuf,
+    folder: PathBuf,
+    /// Single file history, all files of the folder if not set.
+    source_info: Option<SourceInfo>,
+}
+
+/// Locate backups of a file or folder source.
+fn source_backups(source: &SourceRef) -> Result<SourceBackups, StateInitializeError> {
+    let SourceRef {
+        path,
+        name,
+        destination,
+    } = source;
+    let Some(stem) = name.as_deref().map(OsStr::new).or_else(|| path.file_stem())
+    else {
+        return Err(StateInitializeError::NoFileStem {
+            source: path.clone(),
+        });
+    };
+
+    // Folder sources are backed up into a subfolder named by their alias or stem.
+    let folder = destination.join(stem);
+    if path.is_dir() || (!path.exists() && folder.is_dir()) {
+        Ok(SourceBackups {
+            destination: destination.clone(),
+            folder,
+            source_info: None,
+        })
+    } else {
+        let source_info = SourceInfo::with_alias(path, name.as_deref())?;
+        Ok(SourceBackups {
+            destination: destination.clone(),
+            folder: file_backups_folder(destination, path, &source_info)?,
+            source_info: Some(source_info),
+        })
+    }
+}
+
+/// Folder with backups of a file source or a file in a folder source.
+///
+/// Files in folder sources are backed up into a subfolder named by the folder
+/// stem, it's used if the file has no backups in the destination itself.
+fn file_backups_folder(
+    destination: &Path,
+    source: &Path,
+    source_info: &SourceInfo,
+) -> Result<PathBuf, StateInitializeError> {
+    let has_backups = |folder: &Path| -> Result<bool, StateInitializeError> {
+        Ok(folder.is_dir()
+            && read_backups(folder)?
+                .iter()
+                .any(|entry| entry.belongs_to(source_info)))
+    };
+
+    if !has_backups(destination)? {
+        if let Some(parent_stem) = source.parent().and_then(Path::file_stem) {
+            let folder = destination.join(parent_stem);
+            if has_backups(&folder)? {
+                return Ok(folder);
+            }
+        }
+    }
+
+    Ok(destination.to_path_buf())
+}
+
+/// Source file name from backup name parts.
+fn file_name(source_info: &SourceInfo) -> PathBuf {
+    let mut name = source_info.prefix.clone();
+    if let Some(extension) = &source_info.extension {
+        name.push(".");
+        name.push(extension);
+    }
+
+    PathBuf::from(name)
+}
+
+/// Result of writing command output, a closed pipe (e.g. `| head`) ends the
+/// output cleanly.
+fn output_result(result: IoResult<()>) -> Result<(), CommandError> {
+    match result {
+        Err(error) if error.kind() == ErrorKind::BrokenPipe => Ok(()),
+        written => written.map_err(CommandError::Output),
+    }
+}
+
+/// Destination folder and its immediate subfolders with backups of folder sources.
+fn destination_folders(
+    destination: &Path,
+) -> Result<Vec<PathBuf>, StateInitializeError> {
+    let read_dir = destination.read_dir().map_err(|error| {
+        StateInitializeError::ReadDestinationFolder {
+            folder: destination.to_path_buf(),
+            error,
+        }
+    })?;
+
+    let mut folders = read_dir
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    folders.sort();
+    folders.insert(0, destination.to_path_buf());
+
+    Ok(folders)
+}
@@ -0,0 +1,45 @@
+use std::io::{Result as IoResult, Write as _, stdout};
+use std::path::Path;
+
+use super::{CommandError, output_result};
+use crate::args::PresetsOptions;
+use crate::presets::{self, Preset, PresetError};
+use crate::utils::home;
+
+/// Print presets matching the query with their found save location.
+pub fn presets(options: &PresetsOptions) -> Result<(), CommandError> {
+    let found = presets::search(options.query.as_deref())?;
+    if found.is_empty() {
+        return output_result(writeln!(stdout().lock(), "No presets found"));
+    }
+
+    let home = home().ok_or(PresetError::NoHome)?;
+    output_result(print_presets(&found, &home))
+}
+
+/// Print presets as tab separated name, app id, title and found locations.
+fn print_presets(found: &[Preset], home: &Path) -> IoResult<()> {
+    let mut out = stdout().lock();
+    for preset in found {
+        let appid = preset
+            .appid
+            .map_or_else(|| "-".to_owned(), |appid| appid.to_string());
+        let location = preset.locate(home).map_or_else(
+            |_| "-".to_owned(),
+            |sources| {
+                sources
+                    .iter()
+                    .map(|(path, _)| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+        );
+        writeln!(
+            out,
+            "{}\t{appid}\t{}\t{location}",
+            preset.name, preset.title
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,284 @@
+use std::collections::HashSet;
+use std::fs::{read, remove_file, rename, write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{CommandError, destination_folders};
+use crate::args::{NamingScheme, ReindexOptions};
+use crate::dry_run;
+use crate::lock::lock_destination;
+use crate::state::{
+    self, BackupEntry, BackupKind, SourceInfo, read_backups, record_naming,
+};
+
+/// Plan of an unfinished reindex in destination root.
+const PLAN_FILE: &str = ".little-becky.reindex";
+
+/// Extension appended to files moved aside during reindex.
+const MOVED_EXTENSION: &str = "reindex";
+
+/// Renames to run, paths are relative to the destination.
+///
+/// Files are moved aside first, so new names never clash with old ones. The plan
+/// is written before any rename and after files are moved aside, so an
+/// interrupted reindex is resumed from it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Plan {
+    /// Naming of renamed versions given by `--to`, recorded in each folder once
+    /// they are renamed. Each folder keeps its naming without it.
+    #[serde(default)]
+    naming: Option<NamingScheme>,
+    /// All files are moved aside.
+    moved: bool,
+    renames: Vec<Rename>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Rename {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+/// Versions of a single source.
+struct History<'a> {
+    source_info: &'a SourceInfo,
+    entries: Vec<&'a BackupEntry>,
+}
+
+/// Check if a reindex was interrupted, so backups must not be written.
+pub fn has_unfinished_reindex(destination: &Path) -> bool {
+    destination.join(PLAN_FILE).exists()
+}
+
+/// Renumber versions of each source densely ordered by time.
+///
+/// Refuse to run while the destination is used by a watcher.
+pub fn reindex(options: &ReindexOptions) -> Result<(), CommandError> {
+    let destination = &options.destination;
+    let _lock = lock_destination(destination, true)?;
+
+    let plan_path = destination.join(PLAN_FILE);
+    let plan = if plan_path.exists() {
+        log::warn!("Resuming unfinished reindex from {plan_path:?}");
+        read_plan(&plan_path)?
+    } else {
+        let plan = make_plan(destination, options.to)?;
+        if plan.renames.is_empty() {
+            log::info!("Nothing to reindex in {destination:?}");
+            return plan
+                .naming
+                .map_or(Ok(()), |naming| record_folders_naming(destination, naming));
+        }
+        if !dry_run::enabled() {
+            write_plan(&plan_path, &plan)?;
+        }
+        plan
+    };
+
+    if dry_run::enabled() {
+        for entry in &plan.renames {
+            log::info!("Dry run: would rename {:?} -> {:?}", entry.from, entry.to);
+        }
+        return Ok(());
+    }
+
+    let naming = plan.naming;
+    let renamed = run_plan(destination, &plan_path, plan)?;
+    if let Some(scheme) = naming {
+        record_folders_naming(destination, scheme)?;
+    }
+
+    remove_file(&plan_path).map_err(|error| CommandError::Write {
+        path: plan_path.clone(),
+        error,
+    })?;
+
+    log::info!("Reindexed {renamed} file(s) in {destination:?}");
+    Ok(())
+}
+
+/// Plan renames for all histories in the destination and its subfolders.
+fn make_plan(
+    destination: &Path,
+    naming: Option<NamingScheme>,
+) -> Result<Plan, CommandError> {
+    let mut plan = Plan {
+        naming,
+        ..Plan::default()
+    };
+
+    for folder in destination_folders(destination)? {
+        let entries = read_backups(&folder)?;
+        let folder_naming = naming.unwrap_or_else(|| state::naming(&folder));
+
+        for history in histories(&entries) {
+            let mut versions = history.entries;
+            versions.sort_by_key(|entry| (entry.time, entry.file_id));
+
+            for (file_id, entry) in (0..).zip(versions) {
+                let target = history.source_info.versioned_path(
+                    &folder,
+                    file_id,
+                    entry.kind == BackupKind::Tombstone,
+                    folder_naming,
+                );
+
+                if target != entry.path {
+                    plan.renames.push(Rename {
+                        from: relative(destination, &entry.path),
+                        to: relative(destination, &target),
+                    });
+                }
+            }
+        }
+    }
+
+    // Only names of renamed files are freed.
+    let sources = plan
+        .renames
+        .iter()
+        .map(|rename| &rename.from)
+        .collect::<HashSet<_>>();
+    if let Some(taken) = plan.renames.iter().find(|rename| {
+        !sources.contains(&rename.to)
+            && destination.join(&rename.to).symlink_metadata().is_ok()
+    }) {
+        return Err(CommandError::NameTaken {
+            path: destination.join(&taken.to),
+        });
+    }
+
+    Ok(plan)
+}
+
+/// Split entries of a folder into histories of sources.
+fn histories(entries: &[BackupEntry]) -> Vec<History<'_>> {
+    let mut histories: Vec<History<'_>> = vec![];
+
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.kind != BackupKind::Collision)
+    {
+        if let Some(history) = histories
+            .iter_mut()
+            .find(|history| entry.belongs_to(history.source_info))
+        {
+            history.entries.push(entry);
+        } else {
+            histories.push(History {
+                source_info: &entry.source_info,
+                entries: vec![entry],
+            });
+        }
+    }
+
+    histories
+}
+
+/// Move files aside, then give them new names.
+///
+/// Return number of renamed files.
+fn run_plan(
+    destination: &Path,
+    plan_path: &Path,
+    mut plan: Plan,
+) -> Result<usize, CommandError> {
+    let rename_file = |from: &Path, to: &Path| {
+        rename(from, to).map_err(|error| CommandError::Write {
+            path: from.to_path_buf(),
+            error,
+        })
+    };
+
+    if !plan.moved {
+        for entry in &plan.renames {
+            let from = destination.join(&entry.from);
+            let moved = moved_path(&from);
+            if from.symlink_metadata().is_ok() && moved.symlink_metadata().is_err() {
+                rename_file(&from, &moved)?;
+            }
+        }
+
+        plan.moved = true;
+        write_plan(plan_path, &plan)?;
+    }
+
+    let mut renamed: usize = 0;
+    for entry in &plan.renames {
+        let moved = moved_path(&destination.join(&entry.from));
+        if moved.symlink_metadata().is_err() {
+            // Renamed before an interruption.
+            continue;
+        }
+
+        let to = destination.join(&entry.to);
+        if to.symlink_metadata().is_ok() {
+            return Err(CommandError::NameTaken { path: to });
+        }
+
+        rename_file(&moved, &to)?;
+        log::debug!("Renamed {:?} -> {:?}", entry.from, entry.to);
+        renamed += 1;
+    }
+
+    Ok(renamed)
+}
+
+/// Record naming of backups in the destination and its subfolders.
+fn record_folders_naming(
+    destination: &Path,
+    naming: NamingScheme,
+) -> Result<(), CommandError> {
+    for folder in destination_folders(destination)? {
+        record_naming(&folder, naming).map_err(|error| CommandError::Write {
+            path: folder.clone(),
+            error,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Path of a file moved aside.
+fn moved_path(path: &Path) -> PathBuf {
+    let mut moved = path.as_os_str().to_owned();
+    moved.push(".");
+    moved.push(MOVED_EXTENSION);
+    PathBuf::from(moved)
+}
+
+/// Path relative to the destination.
+fn relative(destination: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(destination).unwrap_or(path).to_path_buf()
+}
+
+/// Read a reindex plan.
+fn read_plan(path: &Path) -> Result<Plan, CommandError> {
+    let content = read(path).map_err(|error| CommandError::Read {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    serde_json::from_slice(&content).map_err(|error| CommandError::Plan {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Write a reindex plan atomically.
+fn write_plan(path: &Path, plan: &Plan) -> Result<(), CommandError> {
+    let content =
+        serde_json::to_vec_pretty(plan).map_err(|error| CommandError::Plan {
+            path: path.to_path_buf(),
+            error,
+        })?;
+
+    let temporary = moved_path(path);
+    write(&temporary, content)
+        .and_then(|()| rename(&temporary, path))
+        .map_err(|error| CommandError::Write {
+            path: path.to_path_buf(),
+            error,
+        })
+}
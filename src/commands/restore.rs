@@ -0,0 +1,193 @@
+use std::path::Path;
+
+use super::{CommandError, file_name, source_backups};
+use crate::args::{RestoreOptions, Version};
+use crate::dry_run;
+use crate::lock::lock_destination;
+use crate::process;
+use crate::state::{BackupEntry, BackupKind, SourceInfo, read_backups};
+
+/// Version selected from a source history.
+enum Selected<'a> {
+    Backup(&'a BackupEntry),
+    /// The source was deleted at the selected time.
+    Deleted,
+    Missing,
+}
+
+/// Restore a source file or a whole folder source.
+///
+/// The current file is backed up as a new version first if it isn't yet. The
+/// restored file keeps the modification time of the version, so a running watcher
+/// doesn't back it up again.
+pub fn restore(options: &RestoreOptions) -> Result<(), CommandError> {
+    let _lock = lock_destination(&options.source.destination, false)?;
+    let backups = source_backups(&options.source)?;
+    let entries = read_backups(&backups.folder)?;
+
+    if let Some(source_info) = &backups.source_info {
+        let history = history(&entries, source_info);
+        return match select(&history, options.version) {
+            Selected::Backup(entry) => restore_version(
+                &options.source.path,
+                &backups.folder,
+                source_info,
+                &history,
+                entry,
+            ),
+            Selected::Deleted => Err(CommandError::VersionDeleted {
+                source: options.source.path.clone(),
+            }),
+            Selected::Missing => Err(CommandError::VersionNotFound {
+                source: options.source.path.clone(),
+            }),
+        };
+    }
+
+    if let Version::Id(_) = options.version {
+        return Err(CommandError::IdForFolder {
+            source: options.source.path.clone(),
+        });
+    }
+
+    let mut source_infos: Vec<&SourceInfo> = vec![];
+    for entry in &entries {
+        if entry.kind == BackupKind::Backup
+            && !source_infos.iter().any(|known| {
+                known.prefix == entry.source_info.prefix
+                    && known.extension == entry.source_info.extension
+            })
+        {
+            source_infos.push(&entry.source_info);
+        }
+    }
+
+    if !source_infos.is_empty() {
+        dry_run::create_dir_all(&options.source.path).map_err(|error| {
+            CommandError::Write {
+                path: options.source.path.clone(),
+                error,
+            }
+        })?;
+    }
+
+    let mut restored: usize = 0;
+    for source_info in source_infos {
+        let target = options.source.path.join(file_name(source_info));
+        let history = history(&entries, source_info);
+
+        match select(&history, options.version) {
+            Selected::Backup(entry) => {
+                restore_version(
+                    &target,
+                    &backups.folder,
+                    source_info,
+                    &history,
+                    entry,
+                )?;
+                restored += 1;
+            }
+            Selected::Deleted => {
+                log::info!("Left as is, {target:?} was deleted at the selected time");
+            }
+            Selected::Missing => {
+                log::info!("Left as is, no version matches for {target:?}");
+            }
+        }
+    }
+
+    if restored == 0 {
+        return Err(CommandError::VersionNotFound {
+            source: options.source.path.clone(),
+        });
+    }
+
+    log::info!("Restored {restored} file(s) in {:?}", options.source.path);
+    Ok(())
+}
+
+/// Backups and tombstones of a single source ordered by time.
+///
+/// Imported versions may have ids after newer ones, ids only break ties.
+fn history<'a>(
+    entries: &'a [BackupEntry],
+    source_info: &SourceInfo,
+) -> Vec<&'a BackupEntry> {
+    let mut history = entries
+        .iter()
+        .filter(|entry| entry.kind != BackupKind::Collision)
+        .filter(|entry| entry.belongs_to(source_info))
+        .collect::<Vec<_>>();
+    history.sort_by_key(|entry| (entry.time, entry.file_id));
+    history
+}
+
+/// Select a version from a history ordered by time.
+fn select<'a>(history: &[&'a BackupEntry], version: Version) -> Selected<'a> {
+    let backups = history
+        .iter()
+        .copied()
+        .filter(|entry| entry.kind == BackupKind::Backup)
+        .collect::<Vec<_>>();
+
+    let selected = match version {
+        Version::Id(id) => history.iter().copied().find(|entry| entry.file_id == id),
+        Version::Latest => backups.last().copied(),
+        Version::Previous(previous) => backups
+            .len()
+            .checked_sub(previous + 1)
+            .and_then(|index| backups.get(index).copied()),
+        Version::At(time) => history.iter().copied().rfind(|entry| entry.time <= time),
+    };
+
+    match selected {
+        Some(entry) if entry.kind == BackupKind::Tombstone => Selected::Deleted,
+        Some(entry) => Selected::Backup(entry),
+        None => Selected::Missing,
+    }
+}
+
+/// Back up the current file if needed and copy the version back.
+fn restore_version(
+    target: &Path,
+    folder: &Path,
+    source_info: &SourceInfo,
+    history: &[&BackupEntry],
+    entry: &BackupEntry,
+) -> Result<(), CommandError> {
+    if target.symlink_metadata().is_ok() {
+        let last_id = history.iter().map(|known| known.file_id).max().unwrap_or(0);
+        let last_time = history
+            .iter()
+            .filter(|known| known.kind == BackupKind::Backup)
+            .map(|known| known.time)
+            .max()
+            .unwrap_or(0);
+
+        let snapshot = process::backup_version(
+            folder,
+            &target.to_path_buf(),
+            source_info,
+            last_id,
+            last_time,
+        )
+        .map_err(|error| CommandError::Write {
+            path: folder.to_path_buf(),
+            error,
+        })?;
+
+        if snapshot {
+            log::info!("Current {target:?} backed up before restore");
+        }
+    }
+
+    process::restore_file(&entry.path, target).map_err(|error| {
+        CommandError::Write {
+            path: target.to_path_buf(),
+            error,
+        }
+    })?;
+
+    log::info!("Restored {target:?} from {:?}", entry.path);
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use core::error::Error;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::Error as IoError;
+use std::path::PathBuf;
+
+/// Error of the control socket.
+#[derive(Debug)]
+pub enum ControlError {
+    /// Another watcher listens on the socket.
+    InUse(PathBuf),
+
+    /// Unable to listen on the socket.
+    Bind { path: PathBuf, error: IoError },
+
+    /// Unable to talk to a watcher.
+    Connect { path: PathBuf, error: IoError },
+
+    /// Invalid response of a watcher.
+    Protocol(serde_json::Error),
+
+    /// Control socket is not available on the platform.
+    #[cfg_attr(unix, allow(dead_code, reason = "Non-Unix platforms only"))]
+    Unsupported,
+}
+
+impl Error for ControlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Bind { error, .. } | Self::Connect { error, .. } => Some(error),
+            Self::Protocol(error) => Some(error),
+            Self::InUse(_) | Self::Unsupported => None,
+        }
+    }
+}
+
+impl Display for ControlError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::InUse(path) => {
+                write!(f, "Control socket {path:?} is in use by another process")
+            }
+            Self::Bind { path, error } => {
+                write!(f, "Unable to listen on control socket {path:?}: {error}")
+            }
+            Self::Connect { path, error } => {
+                write!(f, "Unable to reach a watcher at {path:?}: {error}")
+            }
+            Self::Protocol(error) => write!(f, "Invalid control message: {error}"),
+            Self::Unsupported => {
+                write!(f, "Control socket is supported on Unix only")
+            }
+        }
+    }
+}
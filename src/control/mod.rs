@@ -0,0 +1,139 @@
+mod error;
+#[cfg(unix)]
+mod socket;
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+pub use self::error::ControlError;
+#[cfg(unix)]
+pub use self::socket::{
+    ControlContext, send, spawn_control_server, update_control_context,
+};
+#[cfg(not(unix))]
+pub use self::unsupported::{
+    ControlContext, send, spawn_control_server, update_control_context,
+};
+
+use crate::process::BackupOutcome;
+
+/// Control socket name in the destination root.
+const SOCKET_FILE: &str = ".little-becky.sock";
+
+/// Default control socket of a watcher backing up into the destination.
+pub fn default_socket(destination: &Path) -> PathBuf {
+    destination.join(SOCKET_FILE)
+}
+
+/// Request to a running watcher.
+///
+/// Requests and responses are JSON objects, one per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Report registered files.
+    Status,
+    /// Start watching a source.
+    Add { path: PathBuf },
+    /// Stop watching a source.
+    Remove { path: PathBuf },
+    /// Pause backups of a source.
+    Pause { path: PathBuf },
+    /// Resume backups of a source.
+    Resume { path: PathBuf },
+    /// Back up a source even if it's unchanged.
+    Backup { path: PathBuf },
+    /// Stop the watcher.
+    Shutdown,
+}
+
+/// Response of a running watcher.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Registered files, status only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceStatus>,
+    /// Backups made by the request.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outcomes: Vec<OutcomeRecord>,
+}
+
+/// State of a registered file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourceStatus {
+    pub path: PathBuf,
+    /// Id of the last backup.
+    pub id: u64,
+    /// Modification time of the last backup in nanoseconds, `0` if none.
+    pub last_time: u128,
+    pub paused: bool,
+}
+
+/// Backup outcome of a file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutcomeRecord {
+    pub path: PathBuf,
+    pub outcome: String,
+}
+
+impl Response {
+    fn success() -> Self {
+        Self {
+            ok: true,
+            ..Self::default()
+        }
+    }
+
+    fn failure(error: String) -> Self {
+        Self {
+            ok: false,
+            error: Some(error),
+            ..Self::default()
+        }
+    }
+
+    fn with_outcomes(outcomes: Vec<(PathBuf, BackupOutcome)>) -> Self {
+        Self {
+            outcomes: outcomes
+                .into_iter()
+                .map(|(path, outcome)| OutcomeRecord {
+                    path,
+                    outcome: outcome.to_string(),
+                })
+                .collect(),
+            ..Self::success()
+        }
+    }
+}
+
+/// Control socket is available on Unix only.
+#[cfg(not(unix))]
+mod unsupported {
+    use std::path::Path;
+
+    use super::{ControlError, Request, Response};
+    use crate::args::{SourceOptions, WatchSettings};
+
+    /// Settings to resolve and watch sources added at runtime.
+    pub struct ControlContext {
+        pub defaults: SourceOptions,
+        pub settings: WatchSettings,
+    }
+
+    pub const fn update_control_context(_context: ControlContext) {}
+
+    pub fn spawn_control_server(
+        _socket: &Path,
+        _context: ControlContext,
+    ) -> Result<(), ControlError> {
+        Err(ControlError::Unsupported)
+    }
+
+    pub fn send(_socket: &Path, _request: &Request) -> Result<Response, ControlError> {
+        Err(ControlError::Unsupported)
+    }
+}
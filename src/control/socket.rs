@@ -0,0 +1,231 @@
+use std::fs::remove_file;
+use std::io::{
+    BufRead as _, BufReader, Error as IoError, Result as IoResult, Write as _,
+};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, absolute};
+use std::sync::{PoisonError, RwLock};
+use std::thread;
+
+use super::{ControlError, Request, Response, SourceStatus};
+use crate::args::{SourceOptions, WatchSettings, resolve_source};
+use crate::process;
+use crate::state::source_states;
+use crate::utils::ParentPath as _;
+
+/// Settings to resolve and watch sources added at runtime.
+#[derive(Clone)]
+pub struct ControlContext {
+    pub defaults: SourceOptions,
+    pub settings: WatchSettings,
+}
+
+/// Settings of the running server, replaced on configuration reloads.
+static CONTEXT: RwLock<Option<ControlContext>> = RwLock::new(None);
+
+/// Replace settings of the running server, if any.
+pub fn update_control_context(context: ControlContext) {
+    let mut current = CONTEXT.write().unwrap_or_else(PoisonError::into_inner);
+    if current.is_some() {
+        *current = Some(context);
+    }
+}
+
+/// Settings of the running server.
+fn control_context() -> Option<ControlContext> {
+    CONTEXT
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Listen on the control socket in a background thread.
+///
+/// A socket left by a stopped watcher is replaced, a live one is an error.
+pub fn spawn_control_server(
+    socket: &Path,
+    context: ControlContext,
+) -> Result<(), ControlError> {
+    if socket.symlink_metadata().is_ok() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(ControlError::InUse(socket.to_path_buf()));
+        }
+
+        log::debug!("Removing stale control socket {socket:?}");
+        remove_file(socket).map_err(|error| ControlError::Bind {
+            path: socket.to_path_buf(),
+            error,
+        })?;
+    }
+
+    let listener = bind_private(socket).map_err(|error| ControlError::Bind {
+        path: socket.to_path_buf(),
+        error,
+    })?;
+    log::info!("Listening on control socket {socket:?}");
+
+    *CONTEXT.write().unwrap_or_else(PoisonError::into_inner) = Some(context);
+    thread::Builder::new()
+        .name("control".to_owned())
+        .spawn(move || {
+            for incoming in listener.incoming() {
+                match incoming {
+                    Ok(stream) => {
+                        thread::spawn(move || {
+                            if let Err(error) = serve(stream) {
+                                log::debug!("Control connection closed: {error}");
+                            }
+                        });
+                    }
+                    Err(error) => log::error!("Control connection failed: {error}"),
+                }
+            }
+        })
+        .map_err(|error| ControlError::Bind {
+            path: socket.to_path_buf(),
+            error,
+        })?;
+
+    Ok(())
+}
+
+/// Bind a socket accessible by its owner only, other users must not control the
+/// watcher.
+///
+/// The mode is set by the umask while binding, so the socket is never reachable
+/// by others. The umask is process-wide, no other thread writes files yet.
+fn bind_private(socket: &Path) -> IoResult<UnixListener> {
+    // SAFETY: umask only replaces the file mode creation mask of the process.
+    let previous = unsafe { libc::umask(0o177) };
+    let result = UnixListener::bind(socket);
+    // SAFETY: as above, the previous mask is restored.
+    let private = unsafe { libc::umask(previous) };
+    debug_assert_eq!(private, 0o177, "Umask must not change while binding");
+    result
+}
+
+/// Send a request to a running watcher and wait for the response.
+pub fn send(socket: &Path, request: &Request) -> Result<Response, ControlError> {
+    let connect_error = |error| ControlError::Connect {
+        path: socket.to_path_buf(),
+        error,
+    };
+
+    let mut stream = UnixStream::connect(socket).map_err(connect_error)?;
+    let mut line = serde_json::to_string(request).map_err(ControlError::Protocol)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes()).map_err(connect_error)?;
+
+    let mut response = String::new();
+    BufReader::new(stream)
+        .read_line(&mut response)
+        .map_err(connect_error)?;
+
+    serde_json::from_str(&response).map_err(ControlError::Protocol)
+}
+
+/// Answer requests of a connection until it's closed.
+fn serve(stream: UnixStream) -> IoResult<()> {
+    let mut writer = stream.try_clone()?;
+
+    for read in BufReader::new(stream).lines() {
+        let line = read?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = serde_json::from_str::<Request>(&line);
+        log::debug!("Control request: {request:?}");
+        let shutdown = matches!(request, Ok(Request::Shutdown));
+
+        let response = match request {
+            Ok(parsed) => handle(parsed),
+            Err(error) => Response::failure(format!("Invalid request: {error}")),
+        };
+
+        let mut reply = serde_json::to_string(&response).map_err(IoError::other)?;
+        reply.push('\n');
+        writer.write_all(reply.as_bytes())?;
+
+        if shutdown && !process::shutdown() {
+            log::error!("Unable to shut down, sources are not watched");
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a request.
+fn handle(request: Request) -> Response {
+    match request {
+        Request::Status => {
+            let mut sources = source_states()
+                .into_iter()
+                .map(|(path, id, last_time)| SourceStatus {
+                    paused: process::is_paused(&path),
+                    path,
+                    id,
+                    last_time,
+                })
+                .collect::<Vec<_>>();
+            sources.sort_by(|left, right| left.path.cmp(&right.path));
+
+            Response {
+                sources,
+                ..Response::success()
+            }
+        }
+        Request::Add { path } => control_context().map_or_else(
+            || Response::failure("Control server is not running".to_owned()),
+            |context| add(&path, &context),
+        ),
+        Request::Remove { path } => {
+            if process::remove_source(&path) {
+                Response::success()
+            } else {
+                not_source(&path)
+            }
+        }
+        Request::Pause { path } => {
+            if process::pause(&path) {
+                Response::success()
+            } else {
+                not_source(&path)
+            }
+        }
+        Request::Resume { path } => process::resume(&path).map_or_else(
+            || Response::failure(format!("Source {path:?} is not paused")),
+            Response::with_outcomes,
+        ),
+        Request::Backup { path } => process::backup_now(&path)
+            .map_or_else(|| not_source(&path), Response::with_outcomes),
+        // Answered first, the connection stops the main loop afterwards.
+        Request::Shutdown => Response::success(),
+    }
+}
+
+/// Resolve and watch a new source.
+fn add(requested: &Path, context: &ControlContext) -> Response {
+    let path = match absolute(requested) {
+        Ok(resolved) => resolved,
+        Err(error) => return Response::failure(error.to_string()),
+    };
+
+    if process::is_source(&path) || process::is_source(path.parent_path()) {
+        return Response::failure(format!("Source {path:?} is already watched"));
+    }
+
+    let source = match resolve_source(&path, &context.defaults) {
+        Ok(source) => source,
+        Err(error) => return Response::failure(error.to_string()),
+    };
+
+    match process::add_source(&source, context.settings) {
+        Ok(outcomes) => Response::with_outcomes(outcomes),
+        Err(error) => Response::failure(error.to_string()),
+    }
+}
+
+fn not_source(path: &Path) -> Response {
+    Response::failure(format!("Path {path:?} is not a source"))
+}
@@ -0,0 +1,32 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::fs;
+use std::io::Result as IoResult;
+use std::path::Path;
+
+/// Writes are logged instead of done.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable dry run for the whole process.
+pub fn set_enabled(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+    if enabled {
+        log::warn!("Dry run: nothing is written, writes are logged instead");
+    }
+}
+
+/// Check if writes must be logged instead of done.
+pub fn enabled() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Create a folder with its parents unless it's a dry run.
+pub fn create_dir_all(path: &Path) -> IoResult<()> {
+    if enabled() {
+        if !path.is_dir() {
+            log::info!("Dry run: would create folder {path:?}");
+        }
+        return Ok(());
+    }
+
+    fs::create_dir_all(path)
+}
@@ -0,0 +1,360 @@
+use alloc::sync::Arc;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+
+/// File name patterns excluded from folder sources unless disabled.
+///
+/// Covers editor swap and backup files, temporary and lock files, thumbnails and
+/// crash dumps.
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    "*~",
+    ".#*",
+    "*.swp",
+    "*.swx",
+    "4913",
+    "*.tmp",
+    "*.temp",
+    "*.lock",
+    "*.lck",
+    "*.part",
+    "*.crdownload",
+    "*.dmp",
+    "*.mdmp",
+    "Thumbs.db",
+    "desktop.ini",
+    ".DS_Store",
+];
+
+/// File filter for folder sources.
+#[derive(Debug, Clone, Default)]
+pub struct SourceFilter {
+    /// If set, file name must match any of the patterns.
+    include: Option<GlobSet>,
+
+    /// Include patterns as provided to compare filters.
+    include_patterns: Vec<String>,
+
+    /// File name must not match any of the patterns.
+    exclude: GlobSet,
+
+    /// Exclude patterns as provided to report a match.
+    exclude_patterns: Vec<String>,
+
+    /// Maximum file size in bytes.
+    max_file_size: Option<u64>,
+
+    /// Path pattern of a glob source.
+    pattern: Option<Arc<SourcePattern>>,
+}
+
+/// Path pattern of a glob source, relative to its root folder.
+#[derive(Debug)]
+pub struct SourcePattern {
+    /// Longest leading folder without glob characters.
+    root: PathBuf,
+
+    /// Pattern as provided, relative to the root.
+    pattern: String,
+
+    /// Matcher of file paths relative to the root.
+    matcher: GlobMatcher,
+
+    /// Matchers of folder names under the root, up to the first `**`.
+    folders: Vec<GlobMatcher>,
+
+    /// Folders of any depth under `folders` may hold matches.
+    recursive: bool,
+}
+
+impl SourceFilter {
+    /// Build a filter from include and exclude patterns.
+    pub fn new<I, E>(
+        include: I,
+        exclude: E,
+        max_file_size: Option<u64>,
+    ) -> Result<Self, globset::Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        E: IntoIterator,
+        E::Item: AsRef<str>,
+    {
+        let include_patterns = include
+            .into_iter()
+            .map(|pattern| pattern.as_ref().to_owned())
+            .collect::<Vec<_>>();
+        let include_set = build_glob_set(&include_patterns)?;
+        let exclude_patterns = exclude
+            .into_iter()
+            .map(|pattern| pattern.as_ref().to_owned())
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            include: (!include_set.is_empty()).then_some(include_set),
+            include_patterns,
+            exclude: build_glob_set(&exclude_patterns)?,
+            exclude_patterns,
+            max_file_size,
+            pattern: None,
+        })
+    }
+
+    /// Filter of a glob source, files must also match its path pattern.
+    #[must_use]
+    pub fn with_pattern(&self, pattern: SourcePattern) -> Self {
+        Self {
+            pattern: Some(Arc::new(pattern)),
+            ..self.clone()
+        }
+    }
+
+    /// Path pattern of a glob source.
+    pub fn pattern(&self) -> Option<&SourcePattern> {
+        self.pattern.as_deref()
+    }
+
+    /// Check if the file should be backed up.
+    ///
+    /// Rejected paths are logged at trace level.
+    pub fn accepts(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name() else {
+            log::trace!("Filtered out {path:?}: no file name");
+            return false;
+        };
+
+        if let Some(pattern) = &self.pattern {
+            if !pattern.matches(path) {
+                log::trace!("Filtered out {path:?}: doesn't match {pattern}");
+                return false;
+            }
+        }
+
+        if let Some(include) = &self.include {
+            if !include.is_match(name) {
+                log::trace!("Filtered out {path:?}: no include pattern matches");
+                return false;
+            }
+        }
+
+        if let Some(pattern) = self
+            .exclude
+            .matches(name)
+            .first()
+            .and_then(|&index| self.exclude_patterns.get(index))
+        {
+            log::trace!("Filtered out {path:?}: matches exclude pattern {pattern:?}");
+            return false;
+        }
+
+        if let Some(max_file_size) = self.max_file_size {
+            match path.metadata() {
+                Ok(metadata) if metadata.len() > max_file_size => {
+                    log::trace!(
+                        "Filtered out {path:?}: size {} exceeds {max_file_size} bytes",
+                        metadata.len()
+                    );
+                    return false;
+                }
+                Ok(_) | Err(_) => {}
+            }
+        }
+
+        true
+    }
+}
+
+/// Filters are equal if they are built from the same patterns and size limit.
+impl PartialEq for SourceFilter {
+    fn eq(&self, other: &Self) -> bool {
+        self.include_patterns == other.include_patterns
+            && self.exclude_patterns == other.exclude_patterns
+            && self.max_file_size == other.max_file_size
+            && self.pattern.as_deref().map(SourcePattern::parts)
+                == other.pattern.as_deref().map(SourcePattern::parts)
+    }
+}
+
+impl SourcePattern {
+    /// Split a path with glob characters into a root folder and a pattern.
+    ///
+    /// Return `None` if the path has no glob characters.
+    pub fn split(path: &Path) -> Option<Result<Self, globset::Error>> {
+        let mut root = PathBuf::new();
+        let mut pattern = vec![];
+
+        for component in path.components() {
+            let part = component.as_os_str().to_string_lossy();
+            if pattern.is_empty() && !is_glob(&part) {
+                root.push(component);
+            } else {
+                pattern.push(part.into_owned());
+            }
+        }
+
+        if pattern.is_empty() {
+            return None;
+        }
+
+        Some(Self::new(root, &pattern))
+    }
+
+    /// Build a pattern from its components relative to the root.
+    fn new(root: PathBuf, components: &[String]) -> Result<Self, globset::Error> {
+        let pattern = components.join("/");
+        let matcher = path_glob(&pattern)?.compile_matcher();
+
+        // A last `**` matches files in all subfolders as well.
+        let mut folders = vec![];
+        let mut recursive = components.last().is_some_and(|last| last == "**");
+        for component in components.iter().take(components.len() - 1) {
+            if component.contains("**") {
+                recursive = true;
+                break;
+            }
+            folders.push(path_glob(component)?.compile_matcher());
+        }
+
+        Ok(Self {
+            root,
+            pattern,
+            matcher,
+            folders,
+            recursive,
+        })
+    }
+
+    /// Root folder to watch.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Check if matches may be found at any depth under the root.
+    pub const fn is_recursive(&self) -> bool {
+        self.recursive
+    }
+
+    /// Check if a file path matches the pattern.
+    pub fn matches(&self, path: &Path) -> bool {
+        path.strip_prefix(&self.root)
+            .is_ok_and(|relative| self.matcher.is_match(relative))
+    }
+
+    /// Check if files matching the pattern may be found in or under the folder.
+    pub fn may_contain(&self, folder: &Path) -> bool {
+        let Ok(relative) = folder.strip_prefix(&self.root) else {
+            return false;
+        };
+
+        relative.components().enumerate().all(|(index, component)| {
+            self.folders.get(index).map_or(self.recursive, |matcher| {
+                matcher.is_match(component.as_os_str())
+            })
+        })
+    }
+
+    /// Existing folders from `start` down which may contain matches, sorted.
+    ///
+    /// Symbolic links to folders are not followed.
+    pub fn folders(&self, start: &Path) -> Vec<PathBuf> {
+        let mut found = vec![];
+        let mut queue = vec![start.to_path_buf()];
+
+        while let Some(folder) = queue.pop() {
+            if !self.may_contain(&folder) {
+                continue;
+            }
+
+            match folder.read_dir() {
+                Ok(read_dir) => queue.extend(
+                    read_dir
+                        .filter_map(Result::ok)
+                        .filter(|entry| {
+                            entry.file_type().is_ok_and(|kind| kind.is_dir())
+                        })
+                        .map(|entry| entry.path()),
+                ),
+                Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                Err(error) => {
+                    log::warn!("Unable to read {folder:?}: {error}");
+                    continue;
+                }
+            }
+            found.push(folder);
+        }
+
+        found.sort();
+        found
+    }
+
+    /// Check if any existing file matches the pattern.
+    pub fn has_matches(&self) -> bool {
+        self.folders(&self.root)
+            .iter()
+            .filter_map(|folder| folder.read_dir().ok())
+            .flatten()
+            .filter_map(Result::ok)
+            .any(|entry| self.matches(&entry.path()))
+    }
+
+    /// Root and pattern to compare patterns.
+    fn parts(&self) -> (&Path, &str) {
+        (&self.root, &self.pattern)
+    }
+}
+
+impl Display for SourcePattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{:?}", self.root.join(&self.pattern))
+    }
+}
+
+/// Check if a path component has glob characters.
+fn is_glob(component: &str) -> bool {
+    component.contains(['*', '?', '[', '{'])
+}
+
+/// Glob over paths, `*` and `?` don't match a separator.
+fn path_glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
+/// Build a glob set matching file names.
+fn build_glob_set<I>(patterns: I) -> Result<GlobSet, globset::Error>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern.as_ref())?);
+    }
+    builder.build()
+}
+
+/// Parse file size with optional binary suffix: `K`, `M`, `G` or `T`.
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let value = input.trim();
+    let (digits, multiplier) = match value.char_indices().last() {
+        Some((index, suffix)) if suffix.is_ascii_alphabetic() => {
+            let multiplier: u64 = match suffix.to_ascii_uppercase() {
+                'K' => 1 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                'T' => 1 << 40,
+                _ => return Err(format!("unknown size suffix '{suffix}'")),
+            };
+            (value.get(..index).unwrap_or_default(), multiplier)
+        }
+        Some(_) | None => (value, 1),
+    };
+
+    digits
+        .trim()
+        .parse::<u64>()
+        .map_err(|error| format!("invalid size '{value}': {error}"))?
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{value}' is too large"))
+}
@@ -0,0 +1,92 @@
+use core::error::Error;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::dry_run;
+
+/// Lock file in destination root held while backups are written.
+pub const LOCK_FILE: &str = ".little-becky.lock";
+
+/// Held lock of a destination, released on drop.
+///
+/// A dry run doesn't create the lock file, only an existing one is locked.
+#[derive(Debug)]
+pub struct DestinationLock {
+    _file: Option<File>,
+}
+
+/// Destination lock error.
+#[derive(Debug)]
+pub enum LockError {
+    /// Destination is locked by another process.
+    Busy(PathBuf),
+    /// Unable to open or lock the lock file.
+    Io { path: PathBuf, error: IoError },
+}
+
+/// Lock a destination folder without waiting.
+///
+/// Watchers share the lock, commands rewriting the destination need it
+/// exclusively.
+pub fn lock_destination(
+    destination: &Path,
+    exclusive: bool,
+) -> Result<DestinationLock, LockError> {
+    let path = destination.join(LOCK_FILE);
+
+    let file = match OpenOptions::new()
+        .create(!dry_run::enabled())
+        .truncate(false)
+        .write(true)
+        .open(&path)
+    {
+        Ok(file) => file,
+        Err(error) if dry_run::enabled() && error.kind() == ErrorKind::NotFound => {
+            return Ok(DestinationLock { _file: None });
+        }
+        Err(error) => {
+            return Err(LockError::Io { path, error });
+        }
+    };
+
+    let result = if exclusive {
+        file.try_lock()
+    } else {
+        file.try_lock_shared()
+    };
+
+    match result {
+        Ok(()) => Ok(DestinationLock { _file: Some(file) }),
+        Err(TryLockError::WouldBlock) => {
+            Err(LockError::Busy(destination.to_path_buf()))
+        }
+        Err(TryLockError::Error(error)) => Err(LockError::Io { path, error }),
+    }
+}
+
+impl Error for LockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Busy(_) => None,
+            Self::Io { error, .. } => Some(error),
+        }
+    }
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Busy(destination) => {
+                write!(
+                    f,
+                    "Destination {destination:?} is in use by another process"
+                )
+            }
+            Self::Io { path, error } => {
+                write!(f, "Unable to lock {path:?}: {error}")
+            }
+        }
+    }
+}
@@ -4,22 +4,49 @@
     reason = "Print escaped paths"
 )]
 
+extern crate alloc;
+
+use std::fs::remove_file;
+use std::path::PathBuf;
 use std::process::exit;
 
+use toml::ser::Error as TomlError;
+
+use crate::args::{Command, ConfigFile, Source, WatchArgs};
 use crate::logger::setup_logging;
+use crate::process::BackupOutcome;
 
 mod args;
+mod commands;
+mod control;
+mod dry_run;
+mod filter;
+mod lock;
 mod logger;
+mod presets;
 mod process;
+mod reload;
 mod state;
 mod time_utils;
 pub(crate) mod utils;
 
 fn main() {
-    let args = match args::parse_arguments() {
+    let parsed = args::parse_arguments();
+
+    // Set up before resolving, so warnings of sources and configuration are shown.
+    match setup_logging(parsed.log_level().into()) {
+        Ok(()) => {}
+        #[allow(clippy::print_stderr, reason = "No logger set up yet")]
+        Err(error) => {
+            eprintln!("Logger must not be previously set up: {error}");
+            exit(1)
+        }
+    }
+
+    let args = match parsed.resolve() {
         Ok(args) => args,
         Err(error) => {
-            #[allow(clippy::print_stderr, reason = "No logger set up yet")]
+            #[allow(clippy::print_stderr, reason = "Same output as argument errors")]
             {
                 eprintln!("Error: {error}");
             };
@@ -27,30 +54,186 @@ fn main() {
         }
     };
 
+    dry_run::set_enabled(args.dry_run);
+    state::set_naming(args.naming);
+
+    match args.command {
+        Command::PrintConfig(config) => {
+            if let Err(error) = print_config(&config) {
+                log::error!("Unable to print configuration: {error}");
+                exit(2);
+            }
+        }
+        Command::Watch(watch_args) => exit(watch(&watch_args)),
+        Command::Doctor(watch_args) => {
+            if !commands::doctor(&watch_args) {
+                exit(7);
+            }
+        }
+        Command::List(options) => {
+            if let Err(error) = commands::list(&options) {
+                log::error!("Unable to list backups: {error}");
+                exit(2);
+            }
+        }
+        Command::Restore(options) => {
+            if let Err(error) = commands::restore(&options) {
+                log::error!("Unable to restore: {error}");
+                exit(2);
+            }
+        }
+        Command::Diff(options) => {
+            if let Err(error) = commands::diff(&options) {
+                log::error!("Unable to diff: {error}");
+                exit(2);
+            }
+        }
+        Command::Reindex(options) => {
+            if let Err(error) = commands::reindex(&options) {
+                log::error!("Unable to reindex: {error}");
+                exit(2);
+            }
+        }
+        Command::Import(options) => {
+            if let Err(error) = commands::import(&options) {
+                log::error!("Unable to import: {error}");
+                exit(2);
+            }
+        }
+        Command::Ctl(options) => {
+            if let Err(error) = commands::ctl(&options) {
+                log::error!("Unable to control the watcher: {error}");
+                exit(2);
+            }
+        }
+        Command::Presets(options) => {
+            if let Err(error) = commands::presets(&options) {
+                log::error!("Unable to search presets: {error}");
+                exit(2);
+            }
+        }
+    }
+}
+
+/// Back up and watch sources, return exit code.
+fn watch(args: &WatchArgs) -> i32 {
     if args.sources.is_empty() {
-        return;
+        return 0;
     }
 
-    match setup_logging(args.log_level.clone().into()) {
-        Ok(()) => {}
-        #[allow(clippy::print_stderr, reason = "No logger set up yet")]
+    if let Some(destination) = args
+        .destinations
+        .iter()
+        .find(|destination| commands::has_unfinished_reindex(destination))
+    {
+        log::error!("Reindex of {destination:?} is unfinished, run reindex again");
+        return 2;
+    }
+
+    let _locks = match args
+        .destinations
+        .iter()
+        .map(|destination| lock::lock_destination(destination, false))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(locks) => locks,
         Err(error) => {
-            eprintln!("Logger must not be previously set up: {error}");
-            exit(1)
+            log::error!("Initialization error: {error}");
+            return 2;
         }
-    }
+    };
 
     let initial_locations = match state::initialize_state(&args.sources) {
         Ok(additional) => additional,
         Err(error) => {
             log::error!("Initialization error: {error}");
-            exit(2);
+            return 2;
         }
     };
 
-    process::initial_copy(&initial_locations);
-    process::watch(&args.sources, args.fs_timeout);
+    let mut outcomes = process::initial_copy(&initial_locations);
+    if args.once {
+        outcomes.extend(pending_sources(&args.sources));
+        return report_once(&outcomes);
+    }
+
+    if let Some(socket) = &args.control {
+        let context = control::ControlContext {
+            defaults: args.defaults.clone(),
+            settings: args.watch,
+        };
+        if let Err(error) = control::spawn_control_server(socket, context) {
+            log::error!("Initialization error: {error}");
+            return 2;
+        }
+    }
+
+    reload::spawn_reloader(args);
+    if let Some(_lock) = process::watch(&args.sources, args.watch) {
+        log::info!("Shutting down on control request");
+        #[allow(
+            clippy::let_underscore_must_use,
+            reason = "a socket left behind is replaced by the next watcher"
+        )]
+        if let Some(socket) = &args.control {
+            _ = remove_file(socket);
+        }
+        return 0;
+    }
 
     // Unable to start notification listeners
-    exit(3);
+    3
+}
+
+/// Print effective settings as a configuration file.
+fn print_config(config: &ConfigFile) -> Result<(), TomlError> {
+    let content = toml::to_string_pretty(config)?;
+    #[allow(clippy::print_stdout, reason = "Command output")]
+    {
+        print!("{content}");
+    };
+    Ok(())
+}
+
+/// Folder and glob sources waiting for their folder, they have nothing to copy.
+fn pending_sources(sources: &[Source]) -> Vec<(PathBuf, BackupOutcome)> {
+    sources
+        .iter()
+        .filter(|source| match source {
+            Source::Folder(location) | Source::Glob(location) => {
+                !location.source.is_dir()
+            }
+            Source::File(_) => false,
+        })
+        .map(|source| (source.source().clone(), BackupOutcome::Missing))
+        .collect()
+}
+
+/// Print outcome for each source and return exit code.
+///
+/// `0` if any file was copied, `4` if there was nothing to do, `5` on errors,
+/// `6` if a source doesn't exist.
+fn report_once(outcomes: &[(PathBuf, BackupOutcome)]) -> i32 {
+    for (path, outcome) in outcomes {
+        if *outcome == BackupOutcome::Missing {
+            log::warn!("Source doesn't exist: {path:?}");
+        }
+        #[allow(clippy::print_stdout, reason = "Command output")]
+        {
+            println!("{outcome}\t{}", path.display());
+        }
+    }
+
+    let has_outcome =
+        |expected| outcomes.iter().any(|(_, outcome)| *outcome == expected);
+
+    if has_outcome(BackupOutcome::Failed) {
+        5
+    } else if has_outcome(BackupOutcome::Missing) {
+        6
+    } else if has_outcome(BackupOutcome::Copied) {
+        0
+    } else {
+        4
+    }
 }
@@ -0,0 +1,52 @@
+use core::error::Error;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+
+use toml::de::Error as TomlError;
+
+/// Error of game save presets.
+#[derive(Debug)]
+pub enum PresetError {
+    /// Built-in preset list is invalid.
+    Data(TomlError),
+
+    /// No preset has the name or application id.
+    Unknown(String),
+
+    /// Home folder of the user is unknown, locations can't be expanded.
+    NoHome,
+
+    /// None of preset locations exists.
+    NotInstalled {
+        name: String,
+        locations: Vec<PathBuf>,
+    },
+}
+
+impl Error for PresetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Data(error) => Some(error),
+            Self::Unknown(_) | Self::NoHome | Self::NotInstalled { .. } => None,
+        }
+    }
+}
+
+impl Display for PresetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Data(error) => write!(f, "Invalid preset list: {error}"),
+            Self::Unknown(query) => {
+                write!(f, "Unknown preset {query:?}, see `presets search`")
+            }
+            Self::NoHome => write!(f, "Home folder is unknown, HOME is not set"),
+            Self::NotInstalled { name, locations } => {
+                write!(f, "No save location of preset {name:?} exists, tried:")?;
+                for location in locations {
+                    write!(f, " {location:?}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
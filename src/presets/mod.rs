@@ -0,0 +1,379 @@
+mod error;
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+pub use self::error::PresetError;
+
+use crate::filter::SourcePattern;
+use crate::utils::{ParentPath as _, xdg_folder};
+
+/// Known save locations, see the file for variables.
+const PRESETS: &str = include_str!("presets.toml");
+
+#[derive(Debug, Deserialize)]
+struct PresetFile {
+    presets: Vec<Preset>,
+}
+
+/// Save location of a game.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Preset {
+    /// Name used by `--preset` and as source alias.
+    pub name: String,
+    pub title: String,
+    /// Steam application id.
+    pub appid: Option<u32>,
+    /// Alternative locations with variables, the first existing one is used.
+    pub paths: Vec<String>,
+}
+
+/// All known presets.
+pub fn presets() -> Result<Vec<Preset>, PresetError> {
+    toml::from_str::<PresetFile>(PRESETS)
+        .map(|file| file.presets)
+        .map_err(PresetError::Data)
+}
+
+/// Find a preset by its name or Steam application id.
+pub fn find(query: &str) -> Result<Preset, PresetError> {
+    presets()?
+        .into_iter()
+        .find(|preset| {
+            preset.name.eq_ignore_ascii_case(query)
+                || preset.appid.is_some_and(|appid| appid.to_string() == query)
+        })
+        .ok_or_else(|| PresetError::Unknown(query.to_owned()))
+}
+
+/// Presets with the query in their name, title or application id.
+pub fn search(query: Option<&str>) -> Result<Vec<Preset>, PresetError> {
+    let lowercase = query.map(str::to_lowercase);
+
+    Ok(presets()?
+        .into_iter()
+        .filter(|preset| {
+            lowercase.as_deref().is_none_or(|needle| {
+                preset.name.contains(needle)
+                    || preset.title.to_lowercase().contains(needle)
+                    || preset
+                        .appid
+                        .is_some_and(|appid| appid.to_string().contains(needle))
+            })
+        })
+        .collect())
+}
+
+impl Preset {
+    /// Candidate locations with variables expanded, `home` is the user home.
+    ///
+    /// Locations with an unknown variable, e.g. `{proton}` without an application
+    /// id, are left out.
+    pub fn locations(&self, home: &Path) -> Vec<PathBuf> {
+        self.paths
+            .iter()
+            .filter_map(|path| {
+                let expanded = self.expand(path, home);
+                if expanded.is_none() {
+                    log::debug!("Unable to expand {path:?} of preset {:?}", self.name);
+                }
+                expanded
+            })
+            .collect()
+    }
+
+    /// Sources of the first existing location with their aliases.
+    ///
+    /// A location is aliased by the preset name, each subfolder matched by `*` is
+    /// aliased by the name followed by the subfolder name. A location with other
+    /// glob characters is a glob source, it's used if its root exists.
+    pub fn locate(&self, home: &Path) -> Result<Vec<(PathBuf, String)>, PresetError> {
+        let locations = self.locations(home);
+
+        for location in &locations {
+            let parent = location.parent_path();
+            if location.file_name().is_some_and(|name| name == "*")
+                && SourcePattern::split(parent).is_none()
+            {
+                let sources = self.subfolders(parent);
+                if !sources.is_empty() {
+                    return Ok(sources);
+                }
+            } else if let Some(pattern) = SourcePattern::split(location) {
+                if pattern.is_ok_and(|parsed| parsed.root().is_dir()) {
+                    return Ok(vec![(location.clone(), self.name.clone())]);
+                }
+            } else if location.exists() {
+                return Ok(vec![(location.clone(), self.name.clone())]);
+            } else { /* try the next location */
+            }
+        }
+
+        Err(PresetError::NotInstalled {
+            name: self.name.clone(),
+            locations,
+        })
+    }
+
+    /// Subfolders as folder sources, sorted by name.
+    fn subfolders(&self, folder: &Path) -> Vec<(PathBuf, String)> {
+        let Ok(read_dir) = folder.read_dir() else {
+            return vec![];
+        };
+
+        let mut sources = read_dir
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| {
+                let mut path = entry.path().into_os_string();
+                // Written as a folder, it's a folder source even if removed.
+                path.push("/");
+                let alias =
+                    format!("{}-{}", self.name, entry.file_name().to_string_lossy());
+                (PathBuf::from(path), alias)
+            })
+            .collect::<Vec<_>>();
+        sources.sort();
+        sources
+    }
+
+    /// Replace `{variable}` tokens in a path.
+    fn expand(&self, path: &str, home: &Path) -> Option<PathBuf> {
+        let mut expanded = String::new();
+        let mut rest = path;
+
+        while let Some((before, token)) = rest.split_once('{') {
+            let (name, after) = token.split_once('}')?;
+            expanded.push_str(before);
+            expanded.push_str(self.variable(name, home)?.to_str()?);
+            rest = after;
+        }
+        expanded.push_str(rest);
+
+        Some(PathBuf::from(expanded))
+    }
+
+    /// Value of a path variable.
+    fn variable(&self, name: &str, home: &Path) -> Option<PathBuf> {
+        let user = || env::var("USER").or_else(|_| env::var("LOGNAME")).ok();
+
+        match name {
+            "home" => Some(home.to_path_buf()),
+            "xdg_config" => Some(xdg_folder("XDG_CONFIG_HOME", home, ".config")),
+            "xdg_data" => Some(xdg_folder("XDG_DATA_HOME", home, ".local/share")),
+            "steam" => Some(steam_root(home)),
+            "proton" => Some(
+                steam_root(home)
+                    .join("steamapps/compatdata")
+                    .join(self.appid?.to_string())
+                    .join("pfx/drive_c/users/steamuser"),
+            ),
+            "lutris" => Some(
+                home.join("Games")
+                    .join(&self.name)
+                    .join("drive_c/users")
+                    .join(user()?),
+            ),
+            "heroic" => Some(
+                home.join("Games/Heroic/Prefixes/default")
+                    .join(&self.title)
+                    .join("drive_c/users")
+                    .join(user()?),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Steam root, the first existing of native and Flatpak installations.
+fn steam_root(home: &Path) -> PathBuf {
+    let candidates = [
+        home.join(".steam/steam"),
+        home.join(".local/share/Steam"),
+        home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+    ];
+
+    candidates
+        .iter()
+        .find(|candidate| candidate.is_dir())
+        .unwrap_or(&candidates[0])
+        .clone()
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::expect_used,
+    clippy::literal_string_with_formatting_args,
+    reason = "failed setup fails the test, preset paths have `{variable}` tokens"
+)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all};
+    use std::path::{Path, PathBuf};
+    use std::{env, process};
+
+    use super::{Preset, PresetError, presets};
+
+    /// Temporary home folder removed on drop.
+    struct TempHome(PathBuf);
+
+    impl TempHome {
+        #[allow(
+            clippy::let_underscore_must_use,
+            reason = "the folder may not exist yet"
+        )]
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir()
+                .join(format!("little-becky-presets-{}-{name}", process::id()));
+            _ = remove_dir_all(&path);
+            create_dir_all(&path).expect("temporary home is created");
+            Self(path)
+        }
+
+        fn create(&self, folder: &str) {
+            create_dir_all(self.0.join(folder)).expect("folder is created");
+        }
+    }
+
+    impl Drop for TempHome {
+        #[allow(
+            clippy::let_underscore_must_use,
+            reason = "a leftover temporary folder is harmless"
+        )]
+        fn drop(&mut self) {
+            _ = remove_dir_all(&self.0);
+        }
+    }
+
+    fn preset(paths: &[&str]) -> Preset {
+        Preset {
+            name: "game".to_owned(),
+            title: "Game".to_owned(),
+            appid: Some(42),
+            paths: paths.iter().map(|path| (*path).to_owned()).collect(),
+        }
+    }
+
+    fn locate(preset: &Preset, home: &Path) -> Vec<(PathBuf, String)> {
+        preset.locate(home).expect("preset is located")
+    }
+
+    #[test]
+    fn built_in_presets_are_valid() {
+        let presets = presets().expect("presets are parsed");
+        for (index, preset) in presets.iter().enumerate() {
+            assert!(
+                presets
+                    .iter()
+                    .take(index)
+                    .all(|known| known.name != preset.name),
+                "{} is unique",
+                preset.name
+            );
+        }
+    }
+
+    #[test]
+    fn expands_variables() {
+        let home = Path::new("/home/user");
+        let preset = preset(&[]);
+
+        assert_eq!(
+            preset.expand("{home}/saves", home),
+            Some(home.join("saves"))
+        );
+        assert_eq!(
+            preset.expand("{proton}/AppData", home),
+            Some(home.join(
+                ".steam/steam/steamapps/compatdata/42/pfx/drive_c/users/steamuser/AppData"
+            ))
+        );
+        assert_eq!(preset.expand("{unknown}/saves", home), None);
+        assert_eq!(preset.expand("{home/saves", home), None);
+    }
+
+    #[test]
+    fn leaves_out_locations_with_unknown_variables() {
+        let home = Path::new("/home/user");
+        let preset = Preset {
+            appid: None,
+            ..preset(&["{proton}/saves", "{home}/saves"])
+        };
+
+        assert_eq!(preset.locations(home), vec![home.join("saves")]);
+    }
+
+    #[test]
+    fn locates_first_existing_location() {
+        let home = TempHome::new("first");
+        home.create("second/saves");
+        home.create("third/saves");
+        let preset = preset(&[
+            "{home}/first/saves/",
+            "{home}/second/saves/",
+            "{home}/third/saves/",
+        ]);
+
+        assert_eq!(
+            locate(&preset, &home.0),
+            vec![(home.0.join("second/saves/"), "game".to_owned())]
+        );
+    }
+
+    #[test]
+    fn locates_each_subfolder() {
+        let home = TempHome::new("subfolders");
+        home.create("saves/beta");
+        home.create("saves/alpha");
+        let preset = preset(&["{home}/saves/*/"]);
+
+        let root = home.0.join("saves");
+        assert_eq!(
+            locate(&preset, &home.0),
+            vec![
+                (
+                    PathBuf::from(format!("{}/", root.join("alpha").display())),
+                    "game-alpha".to_owned()
+                ),
+                (
+                    PathBuf::from(format!("{}/", root.join("beta").display())),
+                    "game-beta".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn locates_glob_by_its_root() {
+        let home = TempHome::new("glob");
+        let preset = preset(&["{home}/saves/**/*"]);
+        assert!(matches!(
+            preset.locate(&home.0),
+            Err(PresetError::NotInstalled { .. })
+        ));
+
+        home.create("saves");
+        assert_eq!(
+            locate(&preset, &home.0),
+            vec![(home.0.join("saves/**/*"), "game".to_owned())]
+        );
+    }
+
+    #[test]
+    fn reports_tried_locations() {
+        let home = TempHome::new("missing");
+        let preset = preset(&["{home}/saves/", "{home}/other/*/"]);
+
+        let tried = vec![home.0.join("saves/"), home.0.join("other/*/")];
+        let result = preset.locate(&home.0);
+        assert!(
+            matches!(
+                &result,
+                Err(PresetError::NotInstalled { name, locations })
+                    if name == "game" && *locations == tried
+            ),
+            "unexpected {result:?}"
+        );
+    }
+}
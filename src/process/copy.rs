@@ -1,23 +1,70 @@
-use std::fs::copy;
-use std::io::Result as IoResult;
+use core::cell::Cell;
+use std::fs::{File, OpenOptions, read_link, remove_file, rename};
+use std::io::{self, ErrorKind, Result as IoResult};
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+#[cfg(windows)]
+use std::os::windows::fs::symlink_file as symlink;
 use std::path::{Path, PathBuf, absolute};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 
-use crate::args::Location;
-use crate::state::{SourceInfo, StateUpdate, update_state};
+use enum_display::EnumDisplay;
+
+use super::runtime::is_paused;
+use crate::args::{Location, SymlinkPolicy};
+use crate::dry_run;
+use crate::state::{
+    SourceInfo, StateUpdate, persist_naming, tombstone_paths, update_state,
+};
 use crate::time_utils;
 
+/// Result of a backup attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumDisplay)]
+#[enum_display(case = "Lower")]
+pub enum BackupOutcome {
+    /// A new version was taken.
+    Copied,
+    /// Source is not newer than its last backup.
+    Unchanged,
+    /// Source doesn't exist.
+    Missing,
+    /// Backup failed, the error is logged.
+    Failed,
+}
+
 /// Do initial copy for all sources if needed.
-pub fn initial_copy<'a, I>(initial_locations: I)
+///
+/// Return outcome for each source.
+pub fn initial_copy<'a, I>(initial_locations: I) -> Vec<(PathBuf, BackupOutcome)>
 where
     I: IntoIterator<Item = &'a Location>,
 {
     initial_locations
         .into_iter()
-        .for_each(|location| backup_file(&location.source));
+        .map(|location| {
+            let outcome = if location.source.exists() || location.source.is_symlink() {
+                backup_file(&location.source)
+            } else {
+                BackupOutcome::Missing
+            };
+            (location.source.clone(), outcome)
+        })
+        .collect()
+}
+
+/// Serializes backups from event handlers and rescans.
+static BACKUP_LOCK: Mutex<()> = Mutex::new(());
+
+/// Lock backups, so state updates never race and never copy a file twice.
+pub(super) fn lock_backups() -> MutexGuard<'static, ()> {
+    BACKUP_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
 /// Reset known last time for given path on remove events.
-pub fn reset_state(event_path: &PathBuf) {
+///
+/// The skipped id is taken by a tombstone file if enabled, ids taken by backups
+/// are skipped too.
+pub fn reset_state(event_path: &PathBuf, tombstones: bool) {
     let absolute = match absolute(event_path) {
         Ok(path) => path,
         Err(error) => {
@@ -28,32 +75,84 @@ pub fn reset_state(event_path: &PathBuf) {
         }
     };
 
-    update_state(&absolute, |_, _, file_id, _| {
-        StateUpdate::reset(file_id + 1)
+    let _lock = lock_backups();
+    let reset = update_state(&absolute, |destination, source_info, file_id, _| {
+        StateUpdate::reset(free_id(destination, source_info, file_id + 1))
     });
-    {
-        log::info!("Reset {absolute:?}");
+    log::info!("Reset {absolute:?}");
+
+    // Written after the update, which may be retried.
+    if reset && tombstones {
+        for path in tombstone_paths(&absolute) {
+            write_tombstone(&path);
+        }
+    }
+}
+
+/// Write an empty tombstone file, its modification time is the deletion time.
+fn write_tombstone(path: &Path) {
+    if dry_run::enabled() {
+        log::info!("Dry run: would write tombstone {path:?}");
+        return;
+    }
+
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(_) => log::info!("Tombstone written: {path:?}"),
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+            log::debug!("Tombstone already exists: {path:?}");
+        }
+        Err(error) => log::error!("Unable to write tombstone {path:?}: {error}"),
     }
 }
 
 /// Run a backup for a single file.
-pub fn backup_file(event_path: &PathBuf) {
+pub fn backup_file(event_path: &PathBuf) -> BackupOutcome {
+    backup(event_path, false)
+}
+
+/// Back up a file even if it's not newer than its last backup.
+pub(super) fn force_backup(event_path: &PathBuf) -> BackupOutcome {
+    backup(event_path, true)
+}
+
+/// Run a backup for a single file unless its source is paused.
+fn backup(event_path: &PathBuf, force: bool) -> BackupOutcome {
     let path = match absolute(event_path) {
         Ok(path) => path,
         Err(error) => {
             log::error!(
                 "Unable to resolve absolute path for \"{event_path:?}\": {error:#?}"
             );
-            return;
+            return BackupOutcome::Failed;
         }
     };
 
-    update_state(
+    if !force && is_paused(&path) {
+        log::debug!("Paused, skipping {path:?}");
+        return BackupOutcome::Unchanged;
+    }
+
+    let failed = Cell::new(false);
+    let written = Cell::new(None);
+    let _lock = lock_backups();
+    let copied = update_state(
         &path,
         |destination, source_info, file_id, last_time| -> StateUpdate {
-            match try_copy_path(destination, &path, source_info, file_id, last_time) {
+            match try_copy_path(
+                destination,
+                &path,
+                source_info,
+                file_id,
+                last_time,
+                force,
+                &written,
+            ) {
                 Err(error) => {
-                    log::error!("Unable to copy \"{path:?}\": {error}");
+                    log::error!(
+                        "Unable to copy {} to {destination:?}: {error}",
+                        source_info.describe(&path)
+                    );
+                    failed.set(true);
                     StateUpdate::silent_error()
                 }
                 Ok(result) => {
@@ -65,38 +164,318 @@ pub fn backup_file(event_path: &PathBuf) {
             }
         },
     );
+
+    // A destination which failed must be visible even if others are written.
+    if failed.get() {
+        BackupOutcome::Failed
+    } else if copied {
+        BackupOutcome::Copied
+    } else {
+        BackupOutcome::Unchanged
+    }
+}
+
+/// Back up a file outside of the watcher state.
+///
+/// `file_id` and `last_time` describe the latest known backup. Return true if a
+/// new backup was made.
+pub fn backup_version(
+    destination: &Path,
+    source_path: &PathBuf,
+    source_info: &SourceInfo,
+    file_id: u64,
+    last_time: u128,
+) -> IoResult<bool> {
+    let _lock = lock_backups();
+    try_copy_path(
+        destination,
+        source_path,
+        source_info,
+        file_id,
+        last_time,
+        false,
+        &Cell::new(None),
+    )
+    .map(|update| update.has_update())
+}
+
+/// Copy or move a file into a free target name preserving its modification time.
+///
+/// `modified` replaces modification time of the target, e.g. with a date embedded
+/// in the file name.
+pub fn import_file(
+    source: &Path,
+    target: &Path,
+    move_file: bool,
+    modified: Option<u128>,
+) -> IoResult<()> {
+    if target.symlink_metadata().is_ok() {
+        return Err(ErrorKind::AlreadyExists.into());
+    }
+
+    if dry_run::enabled() {
+        if move_file {
+            log::info!("Dry run: would move {source:?} -> {target:?}");
+        } else {
+            log::info!("Dry run: would copy {source:?} -> {target:?}");
+        }
+        return Ok(());
+    }
+
+    let moved = move_file
+        && match rename(source, target) {
+            Ok(()) => true,
+            Err(error) if error.kind() == ErrorKind::CrossesDevices => false,
+            Err(error) => return Err(error),
+        };
+
+    if !moved {
+        copy_file(source, target, source.is_symlink())?;
+        if move_file {
+            remove_file(source)?;
+        }
+    }
+
+    if let Some(time) = modified {
+        OpenOptions::new()
+            .write(true)
+            .open(target)?
+            .set_modified(time_utils::system_time(time))?;
+    }
+
+    Ok(())
 }
 
 /// Try to copy path, return new number and new last modification time.
+///
+/// A forced copy is taken even if the file is not newer than `last_time`.
+/// `written` holds a backup of the source made for another destination. It's
+/// copied instead of the source while the source is unchanged, so the source is
+/// read once for all destinations.
 fn try_copy_path(
     destination: &Path,
     source_path: &PathBuf,
     source_info: &SourceInfo,
     file_id: u64,
     last_time: u128,
+    force: bool,
+    written: &Cell<Option<(PathBuf, u128)>>,
 ) -> IoResult<StateUpdate> {
-    let next_id = if file_id == 0 && last_time == 0 {
+    let first_id = if file_id == 0 && last_time == 0 {
         file_id
     } else {
         file_id + 1
     };
-    log::trace!("{source_path:?} Next id: {next_id:x}");
-    let file_last_modified = time_utils::fs_time(source_path)?;
+    log::trace!("{source_path:?} Next id: {first_id:x}");
+
+    // Only links copied as links are timed by the link itself.
+    let is_link = source_path.is_symlink();
+    let file_last_modified = match source_info.symlinks {
+        SymlinkPolicy::Skip if is_link => {
+            log::debug!("Skipping symlink {source_path:?}");
+            return Ok(StateUpdate::silent_error());
+        }
+        SymlinkPolicy::Follow if is_link => {
+            time_utils::modified_time(&source_path.metadata()?)?
+        }
+        SymlinkPolicy::Skip | SymlinkPolicy::Follow | SymlinkPolicy::CopyLink => {
+            time_utils::fs_time(source_path)?
+        }
+    };
+    let as_link = is_link && source_info.symlinks == SymlinkPolicy::CopyLink;
 
     // Skip old copy
-    if file_last_modified <= last_time {
+    if file_last_modified <= last_time && !force {
         return Ok(StateUpdate::silent_error());
     }
 
-    let mut filename = source_info.prefix.clone();
-    filename.push(format!("_{next_id:x}"));
+    // Destinations of glob matches are created with their first backup.
+    if !destination.is_dir() {
+        dry_run::create_dir_all(destination)?;
+        persist_naming(destination);
+    }
+
+    let next_id = free_id(destination, source_info, first_id);
+    let target_filename = source_info.backup_path(destination, next_id);
+
+    let mut previous = written.take();
+    let copy_source = match &previous {
+        Some((backup, time)) if *time == file_last_modified => backup.as_path(),
+        _ => source_path.as_path(),
+    };
+    let result = copy_file(copy_source, &target_filename, as_link);
+    if previous.is_none() && result.is_ok() {
+        previous = Some((target_filename, file_last_modified));
+    }
+    written.set(previous);
+    result?;
+
+    if force {
+        Ok(StateUpdate::forced_backup(
+            next_id,
+            file_last_modified.max(last_time),
+        ))
+    } else {
+        Ok(StateUpdate::backup(next_id, file_last_modified))
+    }
+}
+
+/// First id from `file_id` not taken by a backup in the destination.
+///
+/// Sources sharing a name (e.g. a followed file) must not overwrite each other.
+fn free_id(destination: &Path, source_info: &SourceInfo, file_id: u64) -> u64 {
+    let mut next_id = file_id;
+    loop {
+        let target_filename = source_info.backup_path(destination, next_id);
+        if !target_filename.exists() {
+            return next_id;
+        }
+        log::debug!("Backup already exists, skipping: {target_filename:?}");
+        next_id += 1;
+    }
+}
+
+/// Atomically replace `target` with a backup, its modification time is kept.
+///
+/// The backup is copied next to the target first, so a watched target is never
+/// seen partially written.
+pub fn restore_file(backup: &Path, target: &Path) -> IoResult<()> {
+    if dry_run::enabled() {
+        if target.symlink_metadata().is_ok() {
+            log::info!("Dry run: would overwrite {target:?} with {backup:?}");
+        } else {
+            log::info!("Dry run: would restore {backup:?} -> {target:?}");
+        }
+        return Ok(());
+    }
+
+    let mut temporary_name = target.as_os_str().to_owned();
+    // Excluded by default and skipped as an atomic save temporary name.
+    temporary_name.push(".restore.tmp");
+    let temporary = PathBuf::from(temporary_name);
+
+    if temporary.symlink_metadata().is_ok() {
+        remove_file(&temporary)?;
+    }
+
+    let result = copy_file(backup, &temporary, backup.is_symlink())
+        .and_then(|()| rename(&temporary, target));
+    #[allow(
+        clippy::let_underscore_must_use,
+        reason = "the copy error is reported, not a failed cleanup"
+    )]
+    if result.is_err() {
+        _ = remove_file(&temporary);
+    }
+
+    result
+}
+
+/// Copy a file preserving its modification time, a symbolic link is copied as
+/// a link with `as_link`, otherwise its target is copied.
+///
+/// Times and permissions are set through the written handle, so read-only
+/// sources are copied too. A partially written target is removed.
+fn copy_file(source: &Path, target: &Path, as_link: bool) -> IoResult<()> {
+    if dry_run::enabled() {
+        log::info!("Dry run: would copy {source:?} -> {target:?}");
+        return Ok(());
+    }
+
+    if as_link {
+        return symlink(&read_link(source)?, target);
+    }
+
+    let mut reader = File::open(source)?;
+    let metadata = reader.metadata()?;
+    let mut writer = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(target)?;
+
+    let result = io::copy(&mut reader, &mut writer)
+        .and_then(|_| writer.set_modified(metadata.modified()?))
+        .and_then(|()| writer.set_permissions(metadata.permissions()));
+    #[allow(
+        clippy::let_underscore_must_use,
+        reason = "the copy error is reported, not a failed cleanup"
+    )]
+    if result.is_err() {
+        drop(writer);
+        _ = remove_file(target);
+    }
+
+    result
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_original: &Path, _link: &Path) -> IoResult<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Symbolic links are not supported",
+    ))
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used, reason = "failed setup fails the test")]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::path::{Path, PathBuf};
+    use std::{env, process};
+
+    use super::free_id;
+    use crate::state::SourceInfo;
+
+    /// Temporary destination folder removed on drop.
+    struct TempDestination(PathBuf);
+
+    impl TempDestination {
+        #[allow(
+            clippy::let_underscore_must_use,
+            reason = "the folder may not exist yet"
+        )]
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir()
+                .join(format!("little-becky-copy-{}-{name}", process::id()));
+            _ = remove_dir_all(&path);
+            create_dir_all(&path).expect("temporary destination is created");
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDestination {
+        #[allow(
+            clippy::let_underscore_must_use,
+            reason = "a leftover temporary folder is harmless"
+        )]
+        fn drop(&mut self) {
+            _ = remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn tombstone_skips_ids_taken_by_backups() {
+        let destination = TempDestination::new("tombstone");
+        let source_info =
+            SourceInfo::try_from(Path::new("save.sav")).expect("source info is parsed");
+        // A renamed file was followed and backed up under ids of the new name.
+        for file_id in [1, 2] {
+            write(source_info.backup_path(&destination.0, file_id), "")
+                .expect("backup is written");
+        }
 
-    let mut target_filename = destination.to_path_buf().join(filename);
+        let file_id = free_id(&destination.0, &source_info, 1);
 
-    if let Some(extension) = &source_info.extension {
-        target_filename.set_extension(extension);
+        assert_eq!(file_id, 3);
+        assert!(!source_info.backup_path(&destination.0, file_id).exists());
     }
 
-    copy(source_path, target_filename)?;
-    Ok(StateUpdate::backup(next_id, file_last_modified))
+    #[test]
+    fn free_id_keeps_an_untaken_id() {
+        let destination = TempDestination::new("untaken");
+        let source_info =
+            SourceInfo::try_from(Path::new("save.sav")).expect("source info is parsed");
+
+        assert_eq!(free_id(&destination.0, &source_info, 1), 1);
+    }
 }
@@ -2,9 +2,11 @@ use core::error::Error as StdError;
 use core::fmt::{Display, Formatter, Result as FmtResult};
 use std::path::PathBuf;
 
+use crate::state::StateInitializeError;
+
 /// Debouncer initialization error.
 #[derive(Debug)]
-pub(super) enum DebouncerInitError {
+pub enum DebouncerInitError {
     Init(PathBuf, notify::Error),
 }
 
@@ -25,3 +27,28 @@ impl Display for DebouncerInitError {
         }
     }
 }
+
+/// Error of a source added to a running watcher.
+#[derive(Debug)]
+pub enum SourceAddError {
+    State(StateInitializeError),
+    Watch(DebouncerInitError),
+}
+
+impl StdError for SourceAddError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match &self {
+            Self::State(error) => Some(error),
+            Self::Watch(error) => Some(error),
+        }
+    }
+}
+
+impl Display for SourceAddError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::State(error) => write!(f, "{error}"),
+            Self::Watch(error) => write!(f, "{error}"),
+        }
+    }
+}
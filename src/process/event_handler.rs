@@ -1,82 +1,262 @@
+use alloc::collections::BTreeSet;
 use std::collections::HashSet as StdHashSet;
 use std::path::{Path, PathBuf};
 
+use notify::EventKind;
+use notify::event::{ModifyKind, RenameMode};
 use notify_debouncer_full::DebounceEventResult;
 
-use crate::state::try_register_path;
+use crate::args::{Location, TrackMode, WatchSettings};
+use crate::state::{follow_file, try_register_path};
 use crate::utils::ParentPath as _;
 
 use super::WATCH_MAP_FOLDER;
 use super::copy::{backup_file, reset_state};
+use super::glob::{discover_glob_folders, forget_glob_folder};
+use super::init::is_watched;
+use super::init::{rearm_dead_watches, watch_parents};
+use super::pending::{check_missing, check_pending};
+use super::rescan::{RescanScope, reconcile};
+
+/// Paths changed in a debouncer event.
+#[derive(Default)]
+struct EventFiles {
+    /// Removed or renamed away paths.
+    removed: StdHashSet<PathBuf>,
+    /// Created, modified or renamed in files.
+    modified: StdHashSet<PathBuf>,
+    /// Created or renamed in folders.
+    folders: StdHashSet<PathBuf>,
+    /// Paths renamed from and to in event order.
+    renamed: Vec<(PathBuf, PathBuf)>,
+    /// Events were lost and sources must be reconciled.
+    rescan: Option<RescanScope>,
+}
+
+/// Lookup for folder source location if file parent is known.
+///
+/// Temporary files of atomic saves are never registered.
+pub(super) fn folder_lookup(source: &Path) -> Option<Location> {
+    if is_atomic_save_temp(source) {
+        log::trace!("Ignoring temporary file {source:?}");
+        return None;
+    }
 
-/// Lookup for destination file if file parent is known.
-fn destination_lookup(source: &Path) -> Option<PathBuf> {
     WATCH_MAP_FOLDER
         .pin()
         .get(&source.parent_path().to_path_buf())
         .cloned()
 }
 
-/// Handler to cover both folders and files.
-pub fn event_handler(event: DebounceEventResult) {
-    let Some((removed, modified)) = collect_event_files(event) else {
-        return;
+/// Extensions of temporary files renamed over a saved file.
+const ATOMIC_SAVE_EXTENSIONS: &[&str] = &["tmp", "temp", "new", "part", "partial"];
+
+/// Check if a file name looks like a temporary file of an atomic save, e.g.
+/// `save.sav.tmp`, `.profile.new` or `save.sav.tmp42`.
+///
+/// Only the name is matched, the file is renamed over the target before the event
+/// is handled, and the target is registered instead.
+fn is_atomic_save_temp(path: &Path) -> bool {
+    let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
     };
+    let name = file_name.strip_prefix('.').unwrap_or(file_name);
+    let Some((_, suffix)) = name.rsplit_once('.') else {
+        return false;
+    };
+    let extension = suffix.to_ascii_lowercase();
+
+    ATOMIC_SAVE_EXTENSIONS.contains(&extension.as_str())
+        || extension
+            .strip_prefix("tmp")
+            .is_some_and(|counter| counter.chars().all(|char| char.is_ascii_digit()))
+}
+
+/// Handler to cover both folders and files.
+pub fn event_handler(event: DebounceEventResult, settings: WatchSettings) {
+    let EventFiles {
+        removed,
+        modified,
+        folders,
+        renamed,
+        rescan,
+    } = collect_event_files(event);
+
+    if !folders.is_empty() {
+        check_pending(&folders, settings);
+        check_missing(|path| folders.contains(path), settings);
+        discover_glob_folders(&folders, settings);
+    }
+
+    if removed.iter().any(|path| is_watched(path)) {
+        rearm_dead_watches(settings);
+    }
+    // Folders of recursive globs have no watch of their own.
+    #[allow(
+        clippy::iter_over_hash_type,
+        reason = "folders are forgotten in any order"
+    )]
+    for path in &removed {
+        forget_glob_folder(path);
+    }
+
+    // Move history of renamed files before a new file takes the old path.
+    if settings.track == TrackMode::File {
+        renamed
+            .iter()
+            .filter(|(_, to)| {
+                folder_lookup(to).is_some_and(|location| location.filter.accepts(to))
+            })
+            .for_each(|(from, to)| {
+                follow_file(from, to);
+            });
+    }
 
     // Lookup registration, but not change it.
     removed
         .into_iter()
-        .filter(|path| try_register_path(path, |_| None))
+        .filter(|path| try_register_path(path, |_| None).is_some())
         .for_each(|path| {
-            reset_state(&path); // sets last_time = 0, keeps number
+            reset_state(&path, settings.tombstones); // sets last_time = 0, keeps number
         });
 
     // Register a file if parent is registered and try_register is true.
-    modified
+    let registered = modified
         .into_iter()
-        .filter(|source| try_register_path(source, destination_lookup))
-        .for_each(|path| {
-            backup_file(&path);
-        });
+        .filter_map(|source| try_register_path(&source, folder_lookup))
+        .collect::<BTreeSet<_>>();
+    watch_parents(&registered, settings);
+    for path in registered {
+        backup_file(&path);
+    }
+
+    if let Some(scope) = rescan {
+        reconcile(&scope, settings);
+    }
 }
 
 /// Collect changed files from a debouncer event, returning removed and modified.
-fn collect_event_files(
-    debouncer_event: DebounceEventResult,
-) -> Option<(StdHashSet<PathBuf>, StdHashSet<PathBuf>)> {
+///
+/// Events are processed in order, so a file renamed over a watched name counts as
+/// a single modification of the target, and the rename origin (usually a temporary
+/// file) is treated as removed and never reported as modified.
+///
+/// Watch errors and rescan requests (e.g. queue overflow) schedule a rescan.
+fn collect_event_files(debouncer_event: DebounceEventResult) -> EventFiles {
     let events = match debouncer_event {
         Ok(events) => events,
         Err(errors) => {
+            let mut rescan = None;
             for error in errors {
                 log::warn!("Watch error: {error:#?}");
+                RescanScope::extend(&mut rescan, &error.paths);
             }
-            return None;
+            return EventFiles {
+                rescan,
+                ..EventFiles::default()
+            };
         }
     };
 
     let mut modified = StdHashSet::new();
     let mut removed = StdHashSet::new();
+    let mut renamed = vec![];
+    let mut rescan = None;
 
     for event in events {
+        let paths = &event.event.paths;
+
+        if event.need_rescan() {
+            log::warn!("Events were lost: {paths:?}");
+            RescanScope::extend(&mut rescan, paths);
+            continue;
+        }
+
         match event.event.kind {
-            notify::EventKind::Create(_) | notify::EventKind::Modify(_) => {
-                modified.extend(
-                    event
-                        .event
-                        .paths
-                        .iter()
-                        .filter(|&path| path.is_file())
-                        .cloned(),
-                );
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if let [from, to] = paths.as_slice() {
+                    log::trace!("Renamed {from:?} -> {to:?}");
+                    modified.remove(from);
+                    removed.insert(from.clone());
+                    modified.insert(to.clone());
+                    renamed.push((from.clone(), to.clone()));
+                }
             }
-            notify::EventKind::Remove(_) => {
-                removed.extend(event.event.paths.iter().cloned());
+            EventKind::Modify(ModifyKind::Name(RenameMode::From))
+            | EventKind::Remove(_) => {
+                for path in paths {
+                    modified.remove(path);
+                    removed.insert(path.clone());
+                }
             }
-            notify::EventKind::Any
-            | notify::EventKind::Access(_)
-            | notify::EventKind::Other => {}
+            // Unpaired rename, i.e. platforms without rename tracking.
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                for path in paths {
+                    if path.exists() {
+                        modified.insert(path.clone());
+                    } else {
+                        modified.remove(path);
+                        removed.insert(path.clone());
+                    }
+                }
+            }
+            EventKind::Create(_) | EventKind::Modify(_) => {
+                modified.extend(paths.iter().cloned());
+            }
+            EventKind::Any | EventKind::Access(_) | EventKind::Other => {}
+        }
+    }
+
+    let folders = modified
+        .iter()
+        .filter(|path| path.is_dir())
+        .cloned()
+        .collect();
+
+    // Replaced files (removed or renamed over) are modified, not removed.
+    // Folder source filters are applied once files are registered.
+    modified.retain(|path| path.is_file() || path.is_symlink());
+    removed.retain(|path| !modified.contains(path));
+
+    EventFiles {
+        removed,
+        modified,
+        folders,
+        renamed,
+        rescan,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::is_atomic_save_temp;
+
+    #[test]
+    fn atomic_save_temp_names() {
+        for name in [
+            "save.sav.tmp",
+            "save.sav.TMP",
+            "save.sav.tmp42",
+            ".profile.new",
+            "save.sav.part",
+        ] {
+            assert!(is_atomic_save_temp(Path::new(name)), "{name}");
         }
     }
 
-    Some((removed, modified))
+    #[test]
+    fn real_extensions_are_not_temp() {
+        for name in [
+            "layout.tmpl",
+            "x.tmpfile",
+            "save.tmp4a",
+            "tmp",
+            ".tmp",
+            "save.sav",
+        ] {
+            assert!(!is_atomic_save_temp(Path::new(name)), "{name}");
+        }
+    }
 }
@@ -0,0 +1,144 @@
+use alloc::sync::Arc;
+use std::collections::HashSet as StdHashSet;
+use std::path::{Path, PathBuf};
+
+use crate::args::{Location, WatchSettings};
+use crate::state::register_folder;
+
+use super::copy::initial_copy;
+use super::init::{add_watch, set_debounce, watch_parents};
+use super::{WATCH_MAP_FOLDER, WATCH_MAP_GLOB};
+
+/// Map a glob source and its existing folders which may contain matches.
+///
+/// Return folders to watch, the root is watched even if it doesn't exist yet.
+pub(super) fn map_glob(location: &Location) -> Vec<PathBuf> {
+    WATCH_MAP_GLOB
+        .pin()
+        .insert(location.source.clone(), location.clone());
+
+    let mut folders = location.glob_folders(&location.source);
+    if folders.is_empty() {
+        folders.push(Location {
+            name: None,
+            ..location.clone()
+        });
+    }
+
+    let map_folder = WATCH_MAP_FOLDER.pin();
+    folders
+        .into_iter()
+        .map(|folder| {
+            set_debounce(&folder.source, folder.fs_timeout);
+            let path = folder.source.clone();
+            map_folder.insert(path.clone(), folder);
+            path
+        })
+        .collect()
+}
+
+/// Watch folders of glob sources appeared in or under created folders, register
+/// their matches and make initial copy.
+pub(super) fn discover_glob_folders(
+    created: &StdHashSet<PathBuf>,
+    settings: WatchSettings,
+) {
+    let globs = WATCH_MAP_GLOB.pin().values().cloned().collect::<Vec<_>>();
+
+    for glob in globs {
+        #[allow(
+            clippy::iter_over_hash_type,
+            reason = "each folder is registered on its own, in any order"
+        )]
+        for folder in created {
+            let start = if folder.starts_with(&glob.source) {
+                folder
+            } else if glob.source.starts_with(folder) {
+                &glob.source
+            } else {
+                continue;
+            };
+
+            for location in glob.glob_folders(start) {
+                if WATCH_MAP_FOLDER.pin().contains_key(&location.source) {
+                    continue;
+                }
+
+                log::info!(
+                    "Watching new folder {:?} of glob source {:?}",
+                    location.source,
+                    glob.source
+                );
+                set_debounce(&location.source, location.fs_timeout);
+                WATCH_MAP_FOLDER
+                    .pin()
+                    .insert(location.source.clone(), location.clone());
+
+                if let Err(error) = add_watch(&location.source, settings, false) {
+                    log::error!("{error}");
+                    continue;
+                }
+
+                match register_folder(&location) {
+                    Ok(locations) => {
+                        watch_parents(
+                            locations.iter().map(|registered| &registered.source),
+                            settings,
+                        );
+                        initial_copy(&locations);
+                    }
+                    Err(error) => log::error!(
+                        "Unable to register folder {:?}: {error}",
+                        location.source
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Forget a deleted folder of a glob source, its root is waited for instead.
+///
+/// Return false if the folder is not a subfolder of a glob source.
+pub(super) fn forget_glob_folder(folder: &Path) -> bool {
+    let is_glob_folder = WATCH_MAP_FOLDER.pin().get(folder).is_some_and(|location| {
+        location.filter.pattern().is_some()
+            && !WATCH_MAP_GLOB.pin().contains_key(folder)
+    });
+
+    if is_glob_folder {
+        log::info!("Folder of glob is gone: {folder:?}");
+        WATCH_MAP_FOLDER.pin().remove(folder);
+    }
+
+    is_glob_folder
+}
+
+/// Remove a glob source and its folders.
+///
+/// Return folders of the glob, `None` if the path is not a glob root.
+pub(super) fn remove_glob(root: &Path) -> Option<Vec<PathBuf>> {
+    let glob = WATCH_MAP_GLOB.pin().remove(root).cloned()?;
+
+    let map_folder = WATCH_MAP_FOLDER.pin();
+    let folders = map_folder
+        .iter()
+        .filter(|(_, location)| Arc::ptr_eq(&location.filter, &glob.filter))
+        .map(|(folder, _)| folder.clone())
+        .collect::<Vec<_>>();
+    for folder in &folders {
+        map_folder.remove(folder);
+    }
+
+    Some(folders)
+}
+
+/// Root of a glob source containing the path.
+pub(super) fn glob_root(path: &Path) -> Option<PathBuf> {
+    WATCH_MAP_GLOB
+        .pin()
+        .keys()
+        .filter(|root| path.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .cloned()
+}
@@ -1,27 +1,58 @@
+use alloc::collections::BTreeSet;
 use core::time::Duration;
-use std::collections::HashSet as StdHashSet;
-use std::path::PathBuf;
+use std::collections::{HashMap as StdHashMap, HashSet as StdHashSet};
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::sync::{MutexGuard, PoisonError};
 
-use notify_debouncer_full::new_debouncer;
+use notify::{Config, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use notify_debouncer_full::{RecommendedCache, new_debouncer_opt};
 
-use crate::args::Source;
-use crate::utils::ParentPath as _;
+use crate::args::{Source, WatchMode, WatchSettings};
+use crate::state::registered_paths;
+use crate::utils::ParentPath;
 
 use super::DebouncerInitError;
-use super::WATCH_MAP_FOLDER;
 use super::event_handler;
+use super::glob::{forget_glob_folder, map_glob};
+use super::pending::{add_pending, map_missing, recheck_pending};
+use super::watcher::release_watches;
+use super::{DEBOUNCE, PENDING, WATCH_MAP_FOLDER, WATCHERS};
 
-pub fn create_all_debouncers<'a, I>(sources: I, fs_timeout: u64) -> Option<impl Sized>
+/// Active watch for a folder.
+///
+/// It's released by the main loop, an event handler may run on its debouncer.
+pub(super) struct Watch {
+    /// Debouncer is held to keep the watch alive, its type is not important.
+    _debouncer: Box<dyn Send>,
+
+    /// The folder is watched only to wait for a pending folder inside it.
+    pending_only: bool,
+}
+
+/// Create debouncers for all sources and keep them in the watcher registry.
+///
+/// Return false if any of them failed.
+pub fn create_all_debouncers<'a, I>(sources: I, settings: WatchSettings) -> bool
 where
     I: IntoIterator<Item = &'a Source>,
 {
-    let debouncer_results: Vec<Result<_, _>> =
-        { create_debouncers(fs_timeout, build_watch_maps(sources)) };
+    let mut file_paths = build_watch_maps(sources);
+    let folder_paths = { WATCH_MAP_FOLDER.pin().keys().cloned().collect::<Vec<_>>() };
+    // Targets of followed links may be outside source folders.
+    file_paths.extend(
+        registered_paths()
+            .iter()
+            .map(|path| path.parent_path().to_path_buf()),
+    );
 
-    if report_errors(&debouncer_results) {
-        return None;
-    }
-    Some(debouncer_results)
+    let results = file_paths
+        .into_iter()
+        .chain(folder_paths)
+        .map(|path| watch_folder(&path, settings))
+        .collect::<Vec<_>>();
+
+    !report_errors(&results)
 }
 
 /// Report all errors and return true if any of them failed.
@@ -40,57 +71,262 @@ fn report_errors<A>(results: &[Result<A, DebouncerInitError>]) -> bool {
     failed
 }
 
-/// Create all debouncers.
+/// Watch a folder, or wait for it if it doesn't exist yet.
+pub(super) fn watch_folder(
+    path: &Path,
+    settings: WatchSettings,
+) -> Result<(), DebouncerInitError> {
+    if path.is_dir() {
+        add_watch(path, settings, false)
+    } else {
+        add_pending(path, settings)
+    }
+}
+
+/// Add a watch for a folder unless it's already watched.
+pub(super) fn add_watch(
+    path: &Path,
+    settings: WatchSettings,
+    pending_only: bool,
+) -> Result<(), DebouncerInitError> {
+    let mut watchers = lock_watchers();
+
+    if let Some(watch) = watchers.get_mut(path) {
+        watch.pending_only &= pending_only;
+        return Ok(());
+    }
+
+    let Some(mode) = recursive_mode(path) else {
+        log::trace!("Watched by its glob root: {path:?}");
+        return Ok(());
+    };
+    let debouncer = create_mode_debouncer(path, settings, mode)?;
+
+    log::trace!("Watching {path:?} ({mode:?})");
+    watchers.insert(
+        path.to_path_buf(),
+        Watch {
+            _debouncer: debouncer,
+            pending_only,
+        },
+    );
+    drop(watchers);
+
+    Ok(())
+}
+
+/// Mode of a folder watch, `None` for a folder under the root of a recursive
+/// glob, the root is watched recursively for all of them.
 ///
-/// Type of debouncers is not really important, we just need to hold them.
-fn create_debouncers(
-    fs_timeout: u64,
-    file_paths: StdHashSet<PathBuf>,
-) -> Vec<Result<impl Sized, DebouncerInitError>> {
-    let mut debouncers: Vec<Result<_, _>> = vec![];
+/// It keeps a deep glob within a single inotify instance.
+fn recursive_mode(path: &Path) -> Option<RecursiveMode> {
+    let map_folder = WATCH_MAP_FOLDER.pin();
+    let recursive = map_folder
+        .get(path)
+        .and_then(|location| location.filter.pattern())
+        .filter(|pattern| pattern.is_recursive());
 
-    let folder_paths = { WATCH_MAP_FOLDER.pin().keys().cloned().collect::<Vec<_>>() };
+    match recursive {
+        Some(pattern) if pattern.root() == path => Some(RecursiveMode::Recursive),
+        Some(_) => None,
+        None => Some(RecursiveMode::NonRecursive),
+    }
+}
 
-    debouncers.extend({
-        file_paths
-            .into_iter()
-            .map(move |path| create_debouncer(&path, fs_timeout, event_handler))
-    });
+/// Watch folders of registered files unless they are already watched, e.g.
+/// targets of followed links outside their folder source.
+pub(super) fn watch_parents<'a, I>(paths: I, settings: WatchSettings)
+where
+    I: IntoIterator<Item = &'a PathBuf>,
+{
+    let folders = paths
+        .into_iter()
+        .map(ParentPath::parent_path)
+        .collect::<BTreeSet<_>>();
 
-    debouncers.extend({
-        folder_paths
-            .into_iter()
-            .map(move |path| create_debouncer(&path, fs_timeout, event_handler))
-    });
+    for folder in folders {
+        if let Err(error) = add_watch(folder, settings, false) {
+            log::error!("{error}");
+        }
+    }
+}
 
-    debouncers
+/// Check if the folder is watched.
+pub(super) fn is_watched(path: &Path) -> bool {
+    lock_watchers().contains_key(path)
+}
+
+/// Re-arm watches of deleted folders: wait for them to appear again.
+pub(super) fn rearm_dead_watches(settings: WatchSettings) {
+    let dead = lock_watchers()
+        .extract_if(|path, _| !path.is_dir())
+        .collect::<Vec<_>>();
+
+    for (path, watch) in &dead {
+        log::warn!("Watched folder is gone: {path:?}");
+        if !watch.pending_only && !forget_glob_folder(path) {
+            if let Err(error) = add_pending(path, settings) {
+                log::error!("{error}");
+            }
+        }
+    }
+    release_watches(dead.into_iter().map(|(_, watch)| watch).collect());
+
+    recheck_pending(settings);
+}
+
+/// Drop a watch if it's used only to wait for pending folders.
+pub(super) fn release_pending_watch(path: &Path) {
+    let mut watchers = lock_watchers();
+
+    if watchers.get(path).is_some_and(|watch| watch.pending_only) {
+        log::trace!("Stop watching {path:?}");
+        release_watches(watchers.remove(path).into_iter().collect());
+    }
+}
+
+/// Drop watches and debounces no source or pending folder needs anymore.
+///
+/// A source added again, e.g. changed by a reload, sets its debounce anew.
+pub(super) fn prune_watches() {
+    let mut needed = registered_paths()
+        .iter()
+        .map(|path| path.parent_path().to_path_buf())
+        .collect::<StdHashSet<_>>();
+    needed.extend(WATCH_MAP_FOLDER.pin().keys().cloned());
+    needed.extend(PENDING.pin().values().cloned());
+
+    DEBOUNCE.pin().retain(|folder, _| needed.contains(folder));
+
+    let unneeded = lock_watchers()
+        .extract_if(|path, _| !needed.contains(path))
+        .map(|(path, watch)| {
+            log::trace!("Stop watching {path:?}");
+            watch
+        })
+        .collect();
+    release_watches(unneeded);
+}
+
+/// Lock watcher registry.
+fn lock_watchers() -> MutexGuard<'static, StdHashMap<PathBuf, Watch>> {
+    WATCHERS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Create debouncer according to watch mode.
+///
+/// In auto mode polling is used if native watches are exhausted.
+fn create_mode_debouncer(
+    path: &Path,
+    settings: WatchSettings,
+    mode: RecursiveMode,
+) -> Result<Box<dyn Send>, DebouncerInitError> {
+    let handler = move |event| event_handler(event, settings);
+    let fs_timeout = DEBOUNCE
+        .pin()
+        .get(path)
+        .copied()
+        .unwrap_or(settings.fs_timeout);
+    let poll_config = Config::default()
+        .with_poll_interval(Duration::from_millis(settings.poll_interval))
+        .with_compare_contents(settings.poll_compare_contents);
+
+    match settings.watch_mode {
+        WatchMode::Native => create_debouncer::<RecommendedWatcher, _>(
+            path,
+            mode,
+            fs_timeout,
+            Config::default(),
+            handler,
+        ),
+        WatchMode::Poll => create_debouncer::<PollWatcher, _>(
+            path,
+            mode,
+            fs_timeout,
+            poll_config,
+            handler,
+        ),
+        WatchMode::Auto => {
+            match create_debouncer::<RecommendedWatcher, _>(
+                path,
+                mode,
+                fs_timeout,
+                Config::default(),
+                handler,
+            ) {
+                Err(DebouncerInitError::Init(_, error)) if is_watch_limit(&error) => {
+                    log::warn!("Native watch limit reached, polling {path:?}: {error}");
+                    create_debouncer::<PollWatcher, _>(
+                        path,
+                        mode,
+                        fs_timeout,
+                        poll_config,
+                        handler,
+                    )
+                }
+                result => result,
+            }
+        }
+    }
+}
+
+/// Check if the error is caused by exhausted inotify watches or instances.
+fn is_watch_limit(error: &notify::Error) -> bool {
+    match &error.kind {
+        notify::ErrorKind::MaxFilesWatch => true,
+        notify::ErrorKind::Io(io_error) => is_limit_error(io_error),
+        notify::ErrorKind::Generic(_)
+        | notify::ErrorKind::PathNotFound
+        | notify::ErrorKind::WatchNotFound
+        | notify::ErrorKind::InvalidConfig(_) => false,
+    }
+}
+
+/// Check if an IO error is caused by exhausted watches (`ENOSPC`) or instances
+/// (`EMFILE`).
+#[cfg(unix)]
+fn is_limit_error(error: &IoError) -> bool {
+    matches!(error.raw_os_error(), Some(libc::ENOSPC | libc::EMFILE))
+}
+
+#[cfg(not(unix))]
+const fn is_limit_error(_error: &IoError) -> bool {
+    false
 }
 
 /// Create debouncer.
-fn create_debouncer<F>(
-    path: &PathBuf,
+fn create_debouncer<W, F>(
+    path: &Path,
+    mode: RecursiveMode,
     fs_timeout: u64,
+    config: Config,
     event_handler: F,
-) -> Result<impl Sized, DebouncerInitError>
+) -> Result<Box<dyn Send>, DebouncerInitError>
 where
+    W: Watcher + Send + 'static,
     F: notify_debouncer_full::DebounceEventHandler,
 {
-    let mut debouncer =
-        match new_debouncer(Duration::from_millis(fs_timeout), None, event_handler) {
-            Ok(debouncer) => debouncer,
-            Err(error) => {
-                return Err(DebouncerInitError::Init(path.clone(), error));
-            }
-        };
+    let mut debouncer = match new_debouncer_opt::<F, W, RecommendedCache>(
+        Duration::from_millis(fs_timeout),
+        None,
+        event_handler,
+        RecommendedCache::new(),
+        config,
+    ) {
+        Ok(debouncer) => debouncer,
+        Err(error) => {
+            return Err(DebouncerInitError::Init(path.to_path_buf(), error));
+        }
+    };
 
-    match debouncer.watch(path, notify::RecursiveMode::NonRecursive) {
+    match debouncer.watch(path, mode) {
         Ok(()) => {}
         Err(error) => {
-            return Err(DebouncerInitError::Init(path.clone(), error));
+            return Err(DebouncerInitError::Init(path.to_path_buf(), error));
         }
     }
 
-    Ok(debouncer)
+    Ok(Box::new(debouncer))
 }
 /// Build watch folder watch maps and return unique known parent folders for files.
 fn build_watch_maps<'a, I>(sources: I) -> StdHashSet<PathBuf>
@@ -104,17 +340,37 @@ where
     for source in sources {
         match source {
             Source::File(location) => {
-                known_file_parents.insert(location.source.parent_path().to_path_buf());
+                map_missing(location);
+                let folder = location.source.parent_path();
+                set_debounce(folder, location.fs_timeout);
+                known_file_parents.insert(folder.to_path_buf());
             }
             Source::Folder(location) => {
+                set_debounce(&location.source, location.fs_timeout);
                 WATCH_MAP_FOLDER.insert(
                     location.source.clone(),
-                    location.destination.clone(),
+                    location.clone(),
                     &guard_folder,
                 );
             }
+            Source::Glob(location) => {
+                map_glob(location);
+            }
         }
     }
 
     known_file_parents
 }
+
+/// Set debounce of a source folder, sources sharing a folder use the first one.
+pub(super) fn set_debounce(folder: &Path, fs_timeout: u64) {
+    let debounce = DEBOUNCE.pin();
+    match debounce.try_insert(folder.to_path_buf(), fs_timeout) {
+        Ok(_) => {}
+        Err(occupied) if *occupied.current == fs_timeout => {}
+        Err(occupied) => log::warn!(
+            "Folder {folder:?} is debounced by {} ms, ignoring {fs_timeout} ms",
+            occupied.current
+        ),
+    }
+}
@@ -1,23 +1,62 @@
 mod copy;
 mod error;
 mod event_handler;
+mod glob;
 mod init;
+mod pending;
+mod rescan;
+mod runtime;
 mod watcher;
 
-pub use copy::initial_copy;
-pub use watcher::watch;
+pub use copy::{
+    BackupOutcome, backup_version, import_file, initial_copy, restore_file,
+};
+pub use error::SourceAddError;
+pub use runtime::{
+    add_source, backup_now, is_paused, is_source, pause, remove_source, resume,
+};
+pub use watcher::{shutdown, watch};
 
 use error::DebouncerInitError;
 use event_handler::event_handler;
-use state::WATCH_MAP_FOLDER;
+use state::{
+    DEBOUNCE, MISSING, PAUSED, PENDING, WATCH_MAP_FOLDER, WATCH_MAP_GLOB, WATCHERS,
+};
 
 /// Global watcher state
 mod state {
-    use papaya::HashMap;
+    use papaya::{HashMap, HashSet};
+    use std::collections::HashMap as StdHashMap;
     use std::path::PathBuf;
-    use std::sync::LazyLock;
+    use std::sync::{LazyLock, Mutex};
 
-    // Folder source to destination required to register additional files in runtime.
-    pub(super) static WATCH_MAP_FOLDER: LazyLock<HashMap<PathBuf, PathBuf>> =
+    use super::init::Watch;
+    use crate::args::Location;
+
+    // Folder source to its location required to register additional files in runtime.
+    pub(super) static WATCH_MAP_FOLDER: LazyLock<HashMap<PathBuf, Location>> =
+        LazyLock::new(HashMap::new);
+
+    // Glob source root to its location required to watch folders created in runtime.
+    pub(super) static WATCH_MAP_GLOB: LazyLock<HashMap<PathBuf, Location>> =
+        LazyLock::new(HashMap::new);
+
+    /// Active watches by folder.
+    pub(super) static WATCHERS: LazyLock<Mutex<StdHashMap<PathBuf, Watch>>> =
+        LazyLock::new(|| Mutex::new(StdHashMap::new()));
+
+    /// Missing folders to their nearest existing ancestor being watched.
+    pub(super) static PENDING: LazyLock<HashMap<PathBuf, PathBuf>> =
         LazyLock::new(HashMap::new);
+
+    /// Missing file sources, a folder appeared at their path is a folder source.
+    pub(super) static MISSING: LazyLock<HashMap<PathBuf, Location>> =
+        LazyLock::new(HashMap::new);
+
+    /// Debounce of source folders in milliseconds, if set per source.
+    pub(super) static DEBOUNCE: LazyLock<HashMap<PathBuf, u64>> =
+        LazyLock::new(HashMap::new);
+
+    /// Sources with paused backups.
+    pub(super) static PAUSED: LazyLock<HashSet<PathBuf>> = LazyLock::new(HashSet::new);
 }
@@ -0,0 +1,166 @@
+use std::collections::HashSet as StdHashSet;
+use std::path::{Path, PathBuf};
+
+use crate::args::{Location, Source, WatchSettings};
+use crate::state::{register_folder, registered_paths, unregister};
+use crate::utils::ParentPath as _;
+
+use super::copy::{backup_file, initial_copy};
+use super::init::{add_watch, release_pending_watch, watch_parents};
+use super::runtime::add_source;
+use super::{DebouncerInitError, MISSING, PENDING, WATCH_MAP_FOLDER};
+
+/// Wait for a missing folder by watching its nearest existing ancestor.
+pub(super) fn add_pending(
+    path: &Path,
+    settings: WatchSettings,
+) -> Result<(), DebouncerInitError> {
+    log::info!("Waiting for folder {path:?}");
+    advance(path, settings)
+}
+
+/// Advance pending folders under created folders.
+pub(super) fn check_pending(folders: &StdHashSet<PathBuf>, settings: WatchSettings) {
+    let targets = PENDING
+        .pin()
+        .keys()
+        .filter(|target| folders.iter().any(|folder| target.starts_with(folder)))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for target in targets {
+        if let Err(error) = advance(&target, settings) {
+            log::error!("{error}");
+        }
+    }
+}
+
+/// Advance all pending folders, e.g. after their watched ancestor was deleted.
+pub(super) fn recheck_pending(settings: WatchSettings) {
+    let targets = PENDING.pin().keys().cloned().collect::<Vec<_>>();
+
+    for target in targets {
+        if let Err(error) = advance(&target, settings) {
+            log::error!("{error}");
+        }
+    }
+}
+
+/// Move the watch for a pending folder as deep as possible, activate the folder
+/// once it exists.
+fn advance(target: &Path, settings: WatchSettings) -> Result<(), DebouncerInitError> {
+    loop {
+        let ancestor = nearest_existing_ancestor(target);
+        if ancestor == target {
+            return activate(target, settings);
+        }
+
+        add_watch(&ancestor, settings, true)?;
+        let replaced = PENDING
+            .pin()
+            .insert(target.to_path_buf(), ancestor.clone())
+            .cloned();
+        if let Some(previous) = replaced.filter(|known| known != &ancestor) {
+            release(&previous);
+        }
+        log::debug!("Watching {ancestor:?} for pending folder {target:?}");
+
+        // Folders may be created before the watch is established.
+        if nearest_existing_ancestor(target) == ancestor {
+            return Ok(());
+        }
+    }
+}
+
+/// Watch appeared folder, register its files and make initial copy.
+fn activate(target: &Path, settings: WatchSettings) -> Result<(), DebouncerInitError> {
+    add_watch(target, settings, false)?;
+    if let Some(ancestor) = PENDING.pin().remove(target).cloned() {
+        release(&ancestor);
+        log::info!("Folder appeared: {target:?}");
+    }
+
+    let folder_location = WATCH_MAP_FOLDER.pin().get(target).cloned();
+    if let Some(location) = folder_location {
+        match register_folder(&location) {
+            Ok(locations) => {
+                watch_parents(
+                    locations.iter().map(|registered| &registered.source),
+                    settings,
+                );
+                initial_copy(&locations);
+            }
+            Err(error) => log::error!("Unable to register folder {target:?}: {error}"),
+        }
+    } else {
+        registered_paths()
+            .iter()
+            .filter(|path| path.parent_path() == target && path.is_file())
+            .for_each(|path| {
+                backup_file(path);
+            });
+        check_missing(|path| path.parent_path() == target, settings);
+    }
+
+    Ok(())
+}
+
+/// Remember a missing file source, it may appear as a folder.
+pub(super) fn map_missing(location: &Location) {
+    if location.source.symlink_metadata().is_err() {
+        MISSING
+            .pin()
+            .insert(location.source.clone(), location.clone());
+    }
+}
+
+/// Turn missing file sources, which appeared as folders, into folder sources.
+pub(super) fn check_missing<F>(appeared: F, settings: WatchSettings)
+where
+    F: Fn(&Path) -> bool,
+{
+    let folders = MISSING
+        .pin()
+        .keys()
+        .filter(|path| appeared(path) && path.is_dir())
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for folder in folders {
+        let Some(missing) = MISSING.pin().remove(&folder).cloned() else {
+            continue;
+        };
+        let Some(location) = missing.as_folder() else {
+            log::error!("Unable to watch {folder:?} as a folder: no folder name");
+            continue;
+        };
+
+        log::info!("Source appeared as a folder: {folder:?}");
+        unregister(|known| known == folder);
+        if let Err(error) = add_source(&Source::Folder(location), settings) {
+            log::error!("Unable to watch folder {folder:?}: {error}");
+        }
+    }
+}
+
+/// Stop waiting for a folder of a removed source.
+pub(super) fn drop_pending(target: &Path) {
+    if let Some(ancestor) = PENDING.pin().remove(target).cloned() {
+        release(&ancestor);
+    }
+}
+
+/// Release ancestor watch if no pending folder needs it.
+fn release(ancestor: &Path) {
+    if !PENDING.pin().values().any(|other| other == ancestor) {
+        release_pending_watch(ancestor);
+    }
+}
+
+/// Nearest existing folder, the path itself included.
+fn nearest_existing_ancestor(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find(|ancestor| ancestor.is_dir())
+        .unwrap_or(path)
+        .to_path_buf()
+}
@@ -0,0 +1,126 @@
+use alloc::collections::BTreeSet;
+use core::time::Duration;
+use std::collections::HashSet as StdHashSet;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::args::WatchSettings;
+use crate::state::{registered_paths, try_register_path};
+use crate::utils::ParentPath as _;
+
+use super::copy::{BackupOutcome, backup_file};
+use super::event_handler::folder_lookup;
+use super::glob::discover_glob_folders;
+use super::init::{rearm_dead_watches, watch_parents};
+use super::{WATCH_MAP_FOLDER, WATCH_MAP_GLOB};
+
+/// Folders to reconcile after lost events.
+#[derive(Debug)]
+pub(super) enum RescanScope {
+    All,
+    Folders(StdHashSet<PathBuf>),
+}
+
+impl RescanScope {
+    /// Extend the scope with folders of given paths, no paths means everything.
+    pub(super) fn extend(scope: &mut Option<Self>, paths: &[PathBuf]) {
+        let folders = paths.iter().map(|path| {
+            if path.is_dir() {
+                path.clone()
+            } else {
+                path.parent_path().to_path_buf()
+            }
+        });
+
+        match scope {
+            Some(Self::All) => {}
+            _ if paths.is_empty() => *scope = Some(Self::All),
+            Some(Self::Folders(known)) => known.extend(folders),
+            None => *scope = Some(Self::Folders(folders.collect())),
+        }
+    }
+
+    fn contains(&self, folder: &Path) -> bool {
+        match self {
+            Self::All => true,
+            Self::Folders(folders) => {
+                folders.iter().any(|known| folder.starts_with(known))
+            }
+        }
+    }
+}
+
+/// Reconcile sources with the filesystem after lost events.
+///
+/// Dead watches are re-armed, new folders of glob sources are watched, new files
+/// in folder sources are registered and every file modified since its last backup
+/// is backed up.
+pub(super) fn reconcile(scope: &RescanScope, settings: WatchSettings) {
+    log::debug!("Rescanning sources: {scope:?}");
+
+    rearm_dead_watches(settings);
+
+    let glob_starts = match scope {
+        RescanScope::All => WATCH_MAP_GLOB.pin().keys().cloned().collect(),
+        RescanScope::Folders(folders) => folders.clone(),
+    };
+    discover_glob_folders(&glob_starts, settings);
+
+    let folders = WATCH_MAP_FOLDER
+        .pin()
+        .keys()
+        .filter(|folder| scope.contains(folder) && folder.is_dir())
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut paths = BTreeSet::new();
+
+    for folder in folders {
+        let read_dir = match folder.read_dir() {
+            Ok(read_dir) => read_dir,
+            Err(error) => {
+                log::warn!("Unable to rescan {folder:?}: {error}");
+                continue;
+            }
+        };
+
+        paths.extend(
+            read_dir
+                .filter_map(Result::ok)
+                .filter_map(|entry| try_register_path(&entry.path(), folder_lookup)),
+        );
+    }
+
+    paths.extend(registered_paths().into_iter().filter(|path| {
+        scope.contains(path.parent_path()) && (path.exists() || path.is_symlink())
+    }));
+
+    watch_parents(&paths, settings);
+    for path in &paths {
+        if backup_file(path) == BackupOutcome::Copied {
+            log::warn!("Change caught by rescan, not by an event: {path:?}");
+        }
+    }
+}
+
+/// Periodically reconcile all sources to catch missed events.
+pub(super) fn spawn_periodic_rescan(settings: WatchSettings) {
+    let Some(interval) = settings.rescan_interval.filter(|&interval| interval > 0)
+    else {
+        return;
+    };
+
+    let result = thread::Builder::new()
+        .name("rescan".to_owned())
+        .spawn(move || {
+            #[allow(clippy::infinite_loop, reason = "Rescan while watching")]
+            loop {
+                thread::sleep(Duration::from_secs(interval));
+                reconcile(&RescanScope::All, settings);
+            }
+        });
+
+    if let Err(error) = result {
+        log::error!("Unable to start periodic rescan: {error}");
+    }
+}
@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+
+use crate::args::{Source, WatchSettings};
+use crate::state::{initialize_state, registered_paths, unregister};
+use crate::utils::ParentPath as _;
+
+use super::copy::{BackupOutcome, backup_file, force_backup, lock_backups};
+use super::glob::{glob_root, map_glob, remove_glob};
+use super::init::{prune_watches, set_debounce, watch_folder, watch_parents};
+use super::pending::{drop_pending, map_missing};
+use super::{
+    MISSING, PAUSED, SourceAddError, WATCH_MAP_FOLDER, WATCH_MAP_GLOB, initial_copy,
+};
+
+/// Check if the path is a folder source, a glob root or a registered file.
+pub fn is_source(path: &Path) -> bool {
+    WATCH_MAP_FOLDER.pin().contains_key(path)
+        || WATCH_MAP_GLOB.pin().contains_key(path)
+        || registered_paths().iter().any(|known| known == path)
+}
+
+/// Check if backups of the file are paused by itself, by its folder source or by
+/// its glob source.
+pub fn is_paused(path: &Path) -> bool {
+    let paused = PAUSED.pin();
+    paused.contains(path)
+        || paused.contains(path.parent_path())
+        || glob_root(path).is_some_and(|root| paused.contains(&root))
+}
+
+/// Start watching a source added to a running watcher and make its initial copy.
+pub fn add_source(
+    source: &Source,
+    settings: WatchSettings,
+) -> Result<Vec<(PathBuf, BackupOutcome)>, SourceAddError> {
+    let folders = match source {
+        Source::File(location) => {
+            map_missing(location);
+            set_debounce(location.source.parent_path(), location.fs_timeout);
+            vec![location.source.parent_path().to_path_buf()]
+        }
+        Source::Folder(location) => {
+            set_debounce(&location.source, location.fs_timeout);
+            WATCH_MAP_FOLDER
+                .pin()
+                .insert(location.source.clone(), location.clone());
+            vec![location.source.clone()]
+        }
+        Source::Glob(location) => map_glob(location),
+    };
+
+    let locations = initialize_state([source]).map_err(SourceAddError::State)?;
+    for folder in &folders {
+        watch_folder(folder, settings).map_err(SourceAddError::Watch)?;
+    }
+    watch_parents(locations.iter().map(|location| &location.source), settings);
+    log::info!("Added source {:?}", source.source());
+
+    Ok(initial_copy(&locations))
+}
+
+/// Stop watching a source, its backups are kept.
+///
+/// Return false if the path is not a source.
+pub fn remove_source(path: &Path) -> bool {
+    // Wait for an in-flight copy.
+    let lock = lock_backups();
+
+    if let Some(folders) = remove_glob(path) {
+        unregister(|known| folders.iter().any(|folder| known.parent_path() == folder));
+        for folder in &folders {
+            drop_pending(folder);
+        }
+    } else if WATCH_MAP_FOLDER.pin().remove(path).is_some() {
+        unregister(|known| known.parent_path() == path);
+        drop_pending(path);
+    } else {
+        MISSING.pin().remove(path);
+        if unregister(|known| known == path).is_empty() {
+            return false;
+        }
+
+        let folder = path.parent_path();
+        if !WATCH_MAP_FOLDER.pin().contains_key(folder)
+            && !registered_paths()
+                .iter()
+                .any(|known| known.parent_path() == folder)
+        {
+            drop_pending(folder);
+        }
+    }
+
+    drop(lock);
+
+    PAUSED.pin().remove(path);
+    prune_watches();
+    log::info!("Removed source {path:?}");
+    true
+}
+
+/// Pause backups of a source, changes are picked up on resume.
+///
+/// Return false if the path is not a source.
+pub fn pause(path: &Path) -> bool {
+    if !is_source(path) {
+        return false;
+    }
+
+    PAUSED.pin().insert(path.to_path_buf());
+    log::info!("Paused {path:?}");
+    true
+}
+
+/// Resume backups of a source and back up files changed while paused.
+///
+/// Return `None` if the path is not paused.
+pub fn resume(path: &Path) -> Option<Vec<(PathBuf, BackupOutcome)>> {
+    if !PAUSED.pin().remove(path) {
+        return None;
+    }
+
+    log::info!("Resumed {path:?}");
+    Some(run_backups(path, backup_file))
+}
+
+/// Back up files of a source even if they are not changed, pause is ignored.
+///
+/// Return `None` if the path is not a source.
+pub fn backup_now(path: &Path) -> Option<Vec<(PathBuf, BackupOutcome)>> {
+    if !is_source(path) {
+        return None;
+    }
+
+    Some(run_backups(path, force_backup))
+}
+
+/// Run backups of the source file or of files registered in the folder or glob
+/// source.
+fn run_backups<F>(path: &Path, backup_fn: F) -> Vec<(PathBuf, BackupOutcome)>
+where
+    F: Fn(&PathBuf) -> BackupOutcome,
+{
+    let is_glob = WATCH_MAP_GLOB.pin().contains_key(path);
+    let mut files = registered_paths()
+        .into_iter()
+        .filter(|known| {
+            known == path
+                || known.parent_path() == path
+                || (is_glob && known.starts_with(path))
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|file| {
+            let outcome = if file.exists() || file.is_symlink() {
+                backup_fn(&file)
+            } else {
+                BackupOutcome::Missing
+            };
+            (file, outcome)
+        })
+        .collect()
+}
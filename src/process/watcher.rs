@@ -1,22 +1,76 @@
-use std::thread;
+use std::sync::mpsc::{Sender, channel};
+use std::sync::{MutexGuard, OnceLock};
 
-use super::init::create_all_debouncers;
-use crate::args::Source;
+use super::copy::lock_backups;
+use super::init::{Watch, create_all_debouncers};
+use super::rescan::spawn_periodic_rescan;
+use crate::args::{Source, WatchSettings};
 
-/// Watch sources.
-pub fn watch<'a, I>(sources: I, fs_timeout: u64)
+/// Requests handled by the main loop.
+pub(super) enum LoopRequest {
+    /// Drop watches outside of event handlers of their own debouncers.
+    Release(Vec<Watch>),
+    /// Stop watching once a running backup is finished.
+    Shutdown,
+}
+
+/// Sender of requests to the main loop, set once it's started.
+static MAIN_LOOP: OnceLock<Sender<LoopRequest>> = OnceLock::new();
+
+/// Watch sources until a shutdown is requested.
+///
+/// Return the backup lock on shutdown, no backup starts while it's held, so the
+/// process may exit. `None` if sources are not watched.
+pub fn watch<'a, I>(
+    sources: I,
+    settings: WatchSettings,
+) -> Option<MutexGuard<'static, ()>>
 where
     I: IntoIterator<Item = &'a Source>,
 {
-    let debouncer_results = create_all_debouncers(sources, fs_timeout);
-    match debouncer_results {
-        Some(_) => {}
-        None => return,
+    let (sender, receiver) = channel();
+    if MAIN_LOOP.set(sender).is_err() {
+        log::error!("Sources are already watched");
+        return None;
+    }
+
+    if !create_all_debouncers(sources, settings) {
+        return None;
+    }
+
+    spawn_periodic_rescan(settings);
+
+    // The sender is never dropped
+    for request in receiver {
+        match request {
+            LoopRequest::Release(watches) => drop(watches),
+            LoopRequest::Shutdown => return Some(lock_backups()),
+        }
+    }
+
+    None
+}
+
+/// Request the main loop to stop watching.
+///
+/// Return false if sources are not watched.
+pub fn shutdown() -> bool {
+    MAIN_LOOP
+        .get()
+        .is_some_and(|sender| sender.send(LoopRequest::Shutdown).is_ok())
+}
+
+/// Drop watches in the main loop, or right away if it's not started.
+pub(super) fn release_watches(watches: Vec<Watch>) {
+    if watches.is_empty() {
+        return;
     }
 
-    #[allow(clippy::infinite_loop, reason = "Waiting for notify events forever")]
-    loop {
-        // NEVER RETURN
-        thread::park();
+    #[allow(
+        clippy::let_underscore_must_use,
+        reason = "the main loop never stops while watching"
+    )]
+    if let Some(sender) = MAIN_LOOP.get() {
+        _ = sender.send(LoopRequest::Release(watches));
     }
 }
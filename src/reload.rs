@@ -0,0 +1,239 @@
+use core::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{
+    DebounceEventResult, Debouncer, RecommendedCache, new_debouncer,
+};
+
+use crate::args::{self, Command, Source, WatchArgs, WatchSettings};
+use crate::commands::has_unfinished_reindex;
+use crate::control::{self, ControlContext};
+use crate::lock::{DestinationLock, lock_destination};
+use crate::process;
+
+/// Debounce of configuration file changes in milliseconds.
+const CONFIG_DEBOUNCE: u64 = 500;
+
+/// Watched sources of the active configuration.
+struct Active {
+    sources: Vec<Source>,
+    destinations: Vec<PathBuf>,
+    settings: WatchSettings,
+    /// Locks of destinations added by reloads.
+    locks: Vec<DestinationLock>,
+}
+
+/// Reload configuration in background when it changes or on SIGHUP.
+pub fn spawn_reloader(args: &WatchArgs) {
+    let (sender, receiver) = channel();
+
+    // Held by the reloader thread to keep the watch alive.
+    let config_watch = args
+        .config_path
+        .as_deref()
+        .and_then(|path| watch_config(path, sender.clone()));
+
+    #[cfg(unix)]
+    spawn_signal_listener(sender);
+    #[cfg(not(unix))]
+    drop(sender);
+
+    let active = Active {
+        sources: args.sources.clone(),
+        destinations: args.destinations.clone(),
+        settings: args.watch,
+        locks: vec![],
+    };
+
+    let spawned = thread::Builder::new()
+        .name("reload".to_owned())
+        .spawn(move || {
+            let _config_watch = config_watch;
+            run(&receiver, active);
+        });
+
+    if let Err(error) = spawned {
+        log::error!("Unable to start configuration reloader: {error}");
+    }
+}
+
+/// Reload on each request, requests arriving during a reload are merged.
+fn run(receiver: &Receiver<()>, mut active: Active) {
+    while receiver.recv().is_ok() {
+        while receiver.try_recv().is_ok() {}
+        reload(&mut active);
+    }
+}
+
+/// Watch the folder of the configuration file for its changes.
+fn watch_config(
+    path: &Path,
+    sender: Sender<()>,
+) -> Option<Debouncer<RecommendedWatcher, RecommendedCache>> {
+    let folder = path.parent()?;
+    let file = path.to_path_buf();
+
+    // Reading the file for a reload is an access event, it's not a change.
+    let handler = move |result: DebounceEventResult| {
+        let changed = result.is_ok_and(|events| {
+            events.iter().any(|event| {
+                !event.kind.is_access()
+                    && event.paths.iter().any(|event_path| event_path == &file)
+            })
+        });
+        #[allow(
+            clippy::let_underscore_must_use,
+            reason = "the reloader runs as long as the watcher"
+        )]
+        if changed {
+            _ = sender.send(());
+        }
+    };
+
+    let mut debouncer =
+        match new_debouncer(Duration::from_millis(CONFIG_DEBOUNCE), None, handler) {
+            Ok(debouncer) => debouncer,
+            Err(error) => {
+                log::warn!("Unable to watch configuration {path:?}: {error}");
+                return None;
+            }
+        };
+
+    match debouncer.watch(folder, RecursiveMode::NonRecursive) {
+        Ok(()) => {
+            log::debug!("Watching configuration {path:?}");
+            Some(debouncer)
+        }
+        Err(error) => {
+            log::debug!("Configuration {path:?} is not watched: {error}");
+            None
+        }
+    }
+}
+
+/// Request a reload on SIGHUP.
+#[cfg(unix)]
+fn spawn_signal_listener(sender: Sender<()>) {
+    use signal_hook::consts::SIGHUP;
+    use signal_hook::iterator::Signals;
+
+    let mut signals = match Signals::new([SIGHUP]) {
+        Ok(signals) => signals,
+        Err(error) => {
+            log::warn!("Unable to listen for SIGHUP: {error}");
+            return;
+        }
+    };
+
+    let spawned = thread::Builder::new()
+        .name("sighup".to_owned())
+        .spawn(move || {
+            for _ in signals.forever() {
+                log::info!("SIGHUP received, reloading configuration");
+                if sender.send(()).is_err() {
+                    return;
+                }
+            }
+        });
+
+    if let Err(error) = spawned {
+        log::warn!("Unable to listen for SIGHUP: {error}");
+    }
+}
+
+/// Resolve configuration again and apply the difference of sources.
+///
+/// An invalid configuration is rejected and the active one is kept. Unchanged
+/// sources keep their watches and counters, changed ones are watched again.
+fn reload(active: &mut Active) {
+    let parsed = match args::reparse_arguments() {
+        Ok(resolved) => resolved,
+        Err(error) => {
+            log::error!("Configuration rejected, keeping the active one: {error}");
+            return;
+        }
+    };
+    let Command::Watch(args) = parsed.command else {
+        return;
+    };
+
+    let mut locks = vec![];
+    let mut destinations = vec![];
+    for destination in &args.destinations {
+        if active.destinations.contains(destination) {
+            continue;
+        }
+
+        if has_unfinished_reindex(destination) {
+            log::error!(
+                "Configuration rejected, reindex of {destination:?} is unfinished"
+            );
+            return;
+        }
+
+        match lock_destination(destination, false) {
+            Ok(lock) => {
+                locks.push(lock);
+                destinations.push(destination.clone());
+            }
+            Err(error) => {
+                log::error!("Configuration rejected: {error}");
+                return;
+            }
+        }
+    }
+
+    if args.watch != active.settings {
+        log::warn!("Changed watch settings apply to added and changed sources only");
+    }
+
+    let removed = active
+        .sources
+        .iter()
+        .filter(|source| !args.sources.contains(source))
+        .collect::<Vec<_>>();
+    let added = args
+        .sources
+        .iter()
+        .filter(|source| !active.sources.contains(source))
+        .collect::<Vec<_>>();
+
+    for source in &removed {
+        process::remove_source(source.source());
+    }
+
+    // Sources failed to be added are retried on the next reload.
+    let mut sources = active
+        .sources
+        .iter()
+        .filter(|source| args.sources.contains(source))
+        .cloned()
+        .collect::<Vec<_>>();
+    let kept = sources.len();
+    for source in &added {
+        match process::add_source(source, args.watch) {
+            Ok(_) => sources.push((*source).clone()),
+            Err(error) => log::error!("Unable to watch {:?}: {error}", source.source()),
+        }
+    }
+
+    log::info!(
+        "Configuration reloaded: {} of {} source(s) added, {} removed",
+        sources.len() - kept,
+        added.len(),
+        removed.len()
+    );
+
+    control::update_control_context(ControlContext {
+        defaults: args.defaults.clone(),
+        settings: args.watch,
+    });
+
+    active.sources = sources;
+    active.settings = args.watch;
+    active.destinations.extend(destinations);
+    active.locks.extend(locks);
+}
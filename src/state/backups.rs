@@ -0,0 +1,160 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::args::SymlinkPolicy;
+use crate::time_utils;
+use crate::utils::ParentPath as _;
+
+use super::structures::TOMBSTONE_EXTENSION;
+use super::{SourceInfo, StateInitializeError};
+
+/// Kind of an entry named as a backup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupKind {
+    /// Backed up file or symbolic link.
+    Backup,
+    /// Marker of a deleted source.
+    Tombstone,
+    /// Not a file, but the name is taken.
+    Collision,
+}
+
+/// Entry in a destination folder named as a backup.
+#[derive(Debug, Clone)]
+pub struct BackupEntry {
+    pub path: PathBuf,
+    /// Backup name parts.
+    pub source_info: SourceInfo,
+    pub file_id: u64,
+    pub kind: BackupKind,
+    /// Modification time of the entry, i.e. source time for backups and deletion
+    /// time for tombstones.
+    pub time: u128,
+    pub size: u64,
+}
+
+impl BackupEntry {
+    /// Check if the entry belongs to the source history.
+    pub fn belongs_to(&self, source_info: &SourceInfo) -> bool {
+        self.source_info.prefix == source_info.prefix
+            && self.source_info.extension == source_info.extension
+    }
+}
+
+/// Parse backup name `<prefix>_<id>[.<extension>][.deleted]`.
+///
+/// Return name parts, id and whether it's a tombstone.
+pub fn parse_backup_name(path: &Path) -> Option<(SourceInfo, u64, bool)> {
+    let is_tombstone = path
+        .extension()
+        .is_some_and(|extension| extension == TOMBSTONE_EXTENSION);
+    let name = if is_tombstone {
+        Path::new(path.file_stem()?)
+    } else {
+        Path::new(path.file_name()?)
+    };
+    let stem = name.file_stem()?.to_str()?;
+
+    // The rest after the last underscore must be a number.
+    let (prefix, suffix) = stem.rsplit_once('_')?;
+    let file_id = super::naming(path.parent_path()).parse_id(suffix)?;
+
+    Some((
+        SourceInfo {
+            prefix: OsString::from(prefix),
+            extension: name.extension().map(ToOwned::to_owned),
+            alias: None,
+            symlinks: SymlinkPolicy::default(),
+        },
+        file_id,
+        is_tombstone,
+    ))
+}
+
+/// Parse user provided hex backup id, `0x` prefix is optional.
+pub fn parse_id(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let digits = trimmed.strip_prefix("0x").unwrap_or(trimmed);
+
+    u64::from_str_radix(digits, 16)
+        .map_err(|error| format!("invalid id '{value}': {error}"))
+}
+
+/// Read all entries named as backups in destination folder.
+pub fn read_backups(
+    destination: &Path,
+) -> Result<Vec<BackupEntry>, StateInitializeError> {
+    let read_dir = match destination.read_dir() {
+        Ok(read_dir) => read_dir,
+        Err(error) => {
+            return Err(StateInitializeError::ReadDestinationFolder {
+                folder: destination.to_path_buf(),
+                error,
+            });
+        }
+    };
+
+    let mut entries = vec![];
+
+    for dir_entry in read_dir {
+        let entry = match dir_entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                return Err(StateInitializeError::ReadDestinationFolder {
+                    folder: destination.to_path_buf(),
+                    error,
+                });
+            }
+        };
+
+        let path = entry.path();
+
+        let Some((source_info, file_id, is_tombstone)) = parse_backup_name(&path)
+        else {
+            continue;
+        };
+
+        // Not followed: links are backups made with `--symlinks copy-link`
+        let metadata = match path.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(error) => {
+                log::warn!("Unable to get file metadata for file {path:?}: {error}");
+                continue;
+            }
+        };
+
+        let time = match time_utils::modified_time(&metadata) {
+            Ok(time) => time,
+            Err(error) => {
+                log::warn!("Unable to get file timestamp: {path:?}: {error}");
+                continue;
+            }
+        };
+
+        #[allow(clippy::filetype_is_file, reason = "Only regular files are supported")]
+        let is_file =
+            metadata.file_type().is_file() || metadata.file_type().is_symlink();
+
+        let kind = match (is_file, is_tombstone) {
+            (false, _) => BackupKind::Collision,
+            (true, true) => BackupKind::Tombstone,
+            (true, false) => BackupKind::Backup,
+        };
+
+        entries.push(BackupEntry {
+            path,
+            source_info,
+            file_id,
+            kind,
+            time,
+            size: metadata.len(),
+        });
+    }
+
+    entries.sort_by_key(|entry| entry.file_id);
+
+    Ok(entries)
+}
@@ -1,9 +1,12 @@
-use crate::args::{Location, Source};
+use crate::args::{Location, Source, SymlinkPolicy};
+use crate::dry_run;
 use crate::state::StateInitializeError;
-use crate::time_utils;
-use std::fs::create_dir_all;
+use crate::utils::ParentPath as _;
+use alloc::sync::Arc;
+use std::fs::canonicalize;
 use std::path::{Path, PathBuf, absolute};
 
+use super::backups::{BackupKind, read_backups};
 use super::{SourceInfo, State};
 
 /// Initialize state by reading file information for source files from arguments.
@@ -18,99 +21,222 @@ where
     let mut file_locations = vec![];
     let guard = super::STATE.guard();
 
-    let mut insert = |path: PathBuf, state: State| {
-        super::STATE.insert(path, state, &guard);
+    let mut insert = |path: PathBuf, states: Vec<State>| {
+        super::STATE.insert(path, states, &guard);
     };
     for source in sources {
+        for destination in &source.location().destinations {
+            super::set_folder_naming(destination, source.location().naming);
+        }
+
         match source {
             Source::File(location) => {
                 file_locations.push(location.clone());
                 register_file_source(
                     &location.source,
-                    &location.destination,
+                    location.name.as_deref(),
+                    &location.destinations,
+                    location.symlinks,
                     &mut insert,
                 )?;
             }
+            Source::Folder(location) if !location.source.is_dir() => {
+                log::debug!("Folder is not registered yet: {:?}", location.source);
+            }
             Source::Folder(location) => {
                 file_locations.extend(register_folder_source(location, &mut insert)?);
             }
+            Source::Glob(location) => {
+                for folder in location.glob_folders(&location.source) {
+                    file_locations
+                        .extend(register_folder_source(&folder, &mut insert)?);
+                }
+            }
         }
     }
 
     Ok(file_locations)
 }
 
-/// Register file source.
+/// Register file source with a state for each destination.
+///
+/// An unreadable destination is skipped while another one is available.
 fn register_file_source<F>(
     source: &Path,
-    destination: &Path,
+    alias: Option<&str>,
+    destinations: &[PathBuf],
+    symlinks: SymlinkPolicy,
     insert: &mut F,
 ) -> Result<(), StateInitializeError>
 where
-    F: FnMut(PathBuf, State),
+    F: FnMut(PathBuf, Vec<State>),
 {
-    let source_info: SourceInfo = SourceInfo::try_from(source)?;
-    let state = scan_existing_backups(destination, source, &source_info)?;
-    if state.last_time() > 0 {
-        log::info!("Initial known state for {source:?}: {state}");
+    let mut source_info = SourceInfo::with_alias(source, alias)?;
+    source_info.symlinks = symlinks;
+    let described = source_info.describe(source);
+
+    let mut states = vec![];
+    let mut first_error = None;
+    for destination in destinations {
+        let mut state = match scan_existing_backups(destination, source, &source_info) {
+            Ok(state) => state,
+            Err(error) => {
+                if destinations.len() > 1 {
+                    log::error!("Skipping destination {destination:?}: {error}");
+                }
+                first_error.get_or_insert(error);
+                continue;
+            }
+        };
+
+        state.refresh_identity(source);
+        if state.last_time() > 0 {
+            if destinations.len() > 1 {
+                log::info!(
+                    "Initial known state for {described} in {destination:?}: {state}"
+                );
+            } else {
+                log::info!("Initial known state for {described}: {state}");
+            }
+        }
+        states.push(state);
+    }
+
+    if let Some(error) = first_error.filter(|_| states.is_empty()) {
+        return Err(error);
     }
 
-    insert(source.to_path_buf(), state);
+    insert(source.to_path_buf(), states);
 
     Ok(())
 }
 
 /// Try to Register a path met during watching.
-pub fn try_register_path<F>(source: &Path, lookup_fn: F) -> bool
+///
+/// Return registered path, which differs from `source` for followed symlinks.
+pub fn try_register_path<F>(source: &Path, lookup_fn: F) -> Option<PathBuf>
 where
-    F: FnOnce(&Path) -> Option<PathBuf>,
+    F: FnOnce(&Path) -> Option<Location>,
 {
     let guard = super::STATE.guard();
 
     log::trace!("Registering additional path {source:?}");
-    if super::STATE.contains_key(source, &guard) {
-        return true;
+    if let Some(states) = super::STATE.get(source, &guard) {
+        let symlinks = states.first()?.source_info().symlinks;
+        return resolve_registered_link(source, symlinks);
     }
 
-    let Some(destination) = lookup_fn(source) else {
-        return false; // ignore unknown path
-    };
+    // ignore unknown path
+    let location = lookup_fn(source)?;
+    let entry = resolve_folder_entry(source, &location)?;
+
+    if !location.filter.accepts(&entry) {
+        return None;
+    }
+
+    if super::STATE.contains_key(&entry, &guard) {
+        return Some(entry);
+    }
 
     #[allow(
         clippy::let_underscore_must_use,
         reason = "ignore insertion if its already done"
     )]
-    let mut insert = |path: PathBuf, state: State| {
-        _ = super::STATE.try_insert(path, state, &guard);
+    let mut insert = |path: PathBuf, states: Vec<State>| {
+        _ = super::STATE.try_insert(path, states, &guard);
     };
 
-    match register_file_source(source, &destination, &mut insert) {
+    match register_file_source(
+        &entry,
+        None,
+        &location.destinations,
+        location.symlinks,
+        &mut insert,
+    ) {
         Ok(()) => {
-            log::trace!("Registered additional path: {source:?} ");
-            true
+            log::trace!("Registered additional path: {entry:?} ");
+            Some(entry)
         }
         Err(error) => {
-            log::error!("Error while registering {source:?}: {error}");
-            false
+            log::error!("Error while registering {entry:?}: {error}");
+            None
         }
     }
 }
 
+/// Register a folder source appeared during watching.
+///
+/// Return known file locations for initial copy.
+pub fn register_folder(
+    location: &Location,
+) -> Result<Vec<Location>, StateInitializeError> {
+    let guard = super::STATE.guard();
+
+    #[allow(
+        clippy::let_underscore_must_use,
+        reason = "ignore insertion if its already done"
+    )]
+    let mut insert = |path: PathBuf, states: Vec<State>| {
+        _ = super::STATE.try_insert(path, states, &guard);
+    };
+
+    register_folder_source(location, &mut insert)
+}
+
 /// Register all files (non-recursive) under folder source.
 fn register_folder_source<F>(
     location: &Location,
     insert: &mut F,
 ) -> Result<Vec<Location>, StateInitializeError>
 where
-    F: FnMut(PathBuf, State),
+    F: FnMut(PathBuf, Vec<State>),
 {
-    create_dir_all(&location.destination).map_err(|error| {
-        StateInitializeError::ReadDestinationFolder {
-            folder: location.destination.clone(),
-            error,
+    for destination in &location.destinations {
+        super::set_folder_naming(destination, location.naming);
+    }
+
+    // Folders of glob sources are created with their first backup.
+    if location.filter.pattern().is_some() {
+        return register_folder_files(location, &location.destinations, insert);
+    }
+
+    let mut destinations = vec![];
+    let mut first_error = None;
+    for destination in &location.destinations {
+        match dry_run::create_dir_all(destination) {
+            Ok(()) => {
+                super::persist_naming(destination);
+                destinations.push(destination.clone());
+            }
+            Err(io_error) => {
+                let error = StateInitializeError::ReadDestinationFolder {
+                    folder: destination.clone(),
+                    error: io_error,
+                };
+                if location.destinations.len() > 1 {
+                    log::error!("Skipping destination {destination:?}: {error}");
+                }
+                first_error.get_or_insert(error);
+            }
         }
-    })?;
+    }
+
+    if let Some(error) = first_error.filter(|_| destinations.is_empty()) {
+        return Err(error);
+    }
 
+    register_folder_files(location, &destinations, insert)
+}
+
+/// Register files under folder source with states in given destinations.
+fn register_folder_files<F>(
+    location: &Location,
+    destinations: &[PathBuf],
+    insert: &mut F,
+) -> Result<Vec<Location>, StateInitializeError>
+where
+    F: FnMut(PathBuf, Vec<State>),
+{
     let read_dir = location.source.read_dir().map_err(|error| {
         StateInitializeError::ReadDestinationFolder {
             folder: location.source.clone(),
@@ -126,28 +252,125 @@ where
                 folder: location.source.clone(),
                 error,
             })?;
-        let source = absolute(entry.path()).map_err(|error| {
+        let path = absolute(entry.path()).map_err(|error| {
             StateInitializeError::ReadDestinationFolder {
                 folder: location.source.clone(),
                 error,
             }
         })?;
 
-        if !source.is_file() {
+        let Some(source) = resolve_folder_entry(&path, location) else {
+            continue;
+        };
+
+        if !location.filter.accepts(&source)
+            || additional
+                .iter()
+                .any(|known: &Location| known.source == source)
+        {
             continue;
         }
 
-        register_file_source(&source, &location.destination, insert)?;
+        register_file_source(&source, None, destinations, location.symlinks, insert)?;
 
         additional.push(Location {
             source,
-            destination: location.destination.clone(),
+            destinations: destinations.to_vec(),
+            name: None,
+            filter: Arc::clone(&location.filter),
+            symlinks: location.symlinks,
+            naming: location.naming,
+            fs_timeout: location.fs_timeout,
         });
     }
 
     Ok(additional)
 }
 
+/// Resolve a file under a folder source according to its symlink policy.
+///
+/// Return a path to register or `None` if the file must be skipped. Followed links
+/// are registered by their target, the folder of a target outside the folder
+/// source is watched too.
+fn resolve_folder_entry(path: &Path, location: &Location) -> Option<PathBuf> {
+    if !path.is_symlink() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    match location.symlinks {
+        SymlinkPolicy::Skip => {
+            log::trace!("Skipping symlink {path:?}");
+            None
+        }
+        SymlinkPolicy::CopyLink => Some(path.to_path_buf()),
+        SymlinkPolicy::Follow => {
+            let target = match canonicalize(path) {
+                Ok(target) => target,
+                Err(error) => {
+                    log::warn!("Skipping broken or looped symlink {path:?}: {error}");
+                    return None;
+                }
+            };
+
+            if !target.is_file() {
+                return None;
+            }
+
+            let folder = canonicalize(&location.source)
+                .unwrap_or_else(|_| location.source.clone());
+            if target.parent_path() != folder {
+                log::info!(
+                    "Following symlink out of folder source {path:?} -> {target:?}"
+                );
+                return Some(target);
+            }
+
+            target.file_name().map(|name| location.source.join(name))
+        }
+    }
+}
+
+/// Apply the symlink policy to a registered file source, which may appear as a
+/// link after it was registered, e.g. a source missing at start.
+///
+/// A followed link moves the state to its target, like links resolved at start.
+/// Return the registered path or `None` if the link must be skipped.
+fn resolve_registered_link(source: &Path, symlinks: SymlinkPolicy) -> Option<PathBuf> {
+    if !source.is_symlink() {
+        return Some(source.to_path_buf());
+    }
+
+    match symlinks {
+        SymlinkPolicy::Skip => {
+            log::trace!("Skipping symlink {source:?}");
+            None
+        }
+        SymlinkPolicy::CopyLink => Some(source.to_path_buf()),
+        SymlinkPolicy::Follow => {
+            let target = match canonicalize(source) {
+                Ok(target) => target,
+                Err(error) => {
+                    log::warn!("Skipping broken or looped symlink {source:?}: {error}");
+                    return None;
+                }
+            };
+
+            if !target.is_file() {
+                return None;
+            }
+
+            // A target registered as a source of its own keeps its state, the
+            // link keeps another one and its contents are copied.
+            if super::update::move_state(source, &target) {
+                log::info!("Following symlink {source:?} -> {target:?}");
+                Some(target)
+            } else {
+                Some(source.to_path_buf())
+            }
+        }
+    }
+}
+
 /// Scan existitng backups to populate state.
 fn scan_existing_backups(
     destination: &Path,
@@ -157,92 +380,46 @@ fn scan_existing_backups(
     let mut number: u64 = 0;
     let mut last_time: u128 = 0;
 
-    let prefix_with_underscore = {
-        let mut prefix = source_info.prefix.clone();
-        prefix.push("_");
-        prefix.to_str().map(ToOwned::to_owned).ok_or_else(|| {
-            StateInitializeError::UTF8ConversionError {
-                source: source.to_path_buf(),
-            }
-        })?
-    };
-
-    let extension = source_info.extension.as_deref();
+    // Backup names are parsed as UTF-8.
+    if source_info.prefix.to_str().is_none() {
+        return Err(StateInitializeError::UTF8ConversionError {
+            source: source.to_path_buf(),
+        });
+    }
 
-    let read_dir = match destination.read_dir() {
-        Ok(read_dir) => read_dir,
-        Err(error) => {
-            return Err(StateInitializeError::ReadDestinationFolder {
-                folder: destination.to_path_buf(),
-                error,
-            });
-        }
+    // Not created by a dry run.
+    let entries = if destination.exists() {
+        read_backups(destination)?
+    } else {
+        vec![]
     };
 
-    for dir_entry in read_dir {
-        let entry = match dir_entry {
-            Ok(entry) => entry,
-            Err(error) => {
-                return Err(StateInitializeError::ReadDestinationFolder {
-                    folder: destination.to_path_buf(),
-                    error,
-                });
-            }
-        };
-
-        let path = entry.path();
+    for entry in entries
+        .into_iter()
+        .filter(|entry| entry.belongs_to(source_info))
+    {
+        let value_num = entry.file_id;
 
-        #[allow(clippy::filetype_is_file, reason = "Only regular files are supported")]
-        let is_file = match entry.file_type() {
-            Ok(file_type) => file_type.is_file(),
-            Err(error) => {
-                log::warn!("Unable to get file type for file {path:?}: {error}");
+        match entry.kind {
+            BackupKind::Collision => {
+                number = value_num + 1;
+                log::warn!("Avoiding potential filename collision: {:?}", entry.path);
                 continue;
             }
-        };
-
-        let file_ext = path.extension();
-
-        let Some(filename) = path.file_stem().and_then(|stem| stem.to_str()) else {
-            log::warn!("Unable to get UTF-8 file stem: {path:?}");
-            continue;
-        };
-
-        if file_ext != extension {
-            continue;
-        }
-
-        // filename should start with given prefix
-        let Some(suffix) = filename.strip_prefix(&prefix_with_underscore) else {
-            continue;
-        };
-
-        // The rest must be a hex number.
-        let Ok(value_num) = u64::from_str_radix(suffix, 16) else {
-            continue;
-        };
-
-        if !is_file {
-            number = value_num + 1;
-            log::warn!("Avoiding potential filename collision: {path:?}");
-            continue;
-        }
-
-        // get last time
-        let last_time_fs = match time_utils::fs_time(&path) {
-            Ok(last_time_fs) => last_time_fs,
-            Err(error) => {
-                log::warn!("Unable to get file timestamp: {path:?}: {error}");
+            // Deletion takes an id, but it's not a backup
+            BackupKind::Tombstone => {
+                number = number.max(value_num);
                 continue;
             }
-        };
+            BackupKind::Backup => {}
+        }
 
         // Either number of last time wins :P
         if value_num >= number {
             number = value_num;
         }
-        if last_time_fs > last_time {
-            last_time = last_time_fs;
+        if entry.time > last_time {
+            last_time = entry.time;
         }
     }
 
@@ -1,20 +1,127 @@
+mod backups;
 mod error;
 mod init;
 mod structures;
 mod update;
 
-use std::path::PathBuf;
-use std::sync::LazyLock;
+use std::fs::{read_to_string, write};
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, OnceLock};
 
+use clap::ValueEnum as _;
+
+use crate::args::NamingScheme;
+use crate::dry_run;
+
+pub use self::backups::{BackupEntry, BackupKind, parse_id, read_backups};
 pub use self::error::StateInitializeError;
-pub use self::init::{initialize_state, try_register_path};
+pub use self::init::{initialize_state, register_folder, try_register_path};
 pub use self::structures::{SourceInfo, StateUpdate};
-pub use self::update::update_state;
+pub use self::update::{
+    follow_file, registered_paths, source_states, tombstone_paths, unregister,
+    update_state,
+};
 
 use self::structures::State;
 
 use papaya::HashMap as PapayaHashMap;
 
-/// Static state cell.
-static STATE: LazyLock<PapayaHashMap<PathBuf, State>> =
+/// Static state cell, a state for each destination of a source.
+static STATE: LazyLock<PapayaHashMap<PathBuf, Vec<State>>> =
     LazyLock::new(PapayaHashMap::new);
+
+/// Naming of backup files, set once from arguments.
+static NAMING: OnceLock<NamingScheme> = OnceLock::new();
+
+/// Set naming of backup files, only the first call has effect.
+pub fn set_naming(naming: NamingScheme) {
+    if NAMING.set(naming).is_err() {
+        log::debug!("Naming is already set, ignoring {naming:?}");
+    }
+}
+
+/// Naming of backup files by destination folder, if it's set or recorded.
+static FOLDER_NAMING: LazyLock<PapayaHashMap<PathBuf, NamingScheme>> =
+    LazyLock::new(PapayaHashMap::new);
+
+/// File in a destination folder with naming of its backups, written unless it's
+/// the default one.
+const NAMING_FILE: &str = ".little-becky.naming";
+
+/// Set naming of backup files in a destination folder.
+///
+/// Ids in a folder are parsed with a single naming: the recorded one, or the
+/// first one set, which is recorded.
+pub fn set_folder_naming(folder: &Path, naming: NamingScheme) {
+    let current = *FOLDER_NAMING
+        .pin()
+        .get_or_insert_with(folder.to_path_buf(), || {
+            recorded_naming(folder).unwrap_or(naming)
+        });
+
+    if current == naming {
+        persist_naming(folder);
+    } else {
+        log::warn!("Backups in {folder:?} are named {current:?}, ignoring {naming:?}");
+    }
+}
+
+/// Naming of backup files in a destination folder.
+pub fn naming(folder: &Path) -> NamingScheme {
+    let folder_naming = FOLDER_NAMING.pin();
+    if let Some(naming) = folder_naming.get(folder) {
+        return *naming;
+    }
+
+    if let Some(naming) = recorded_naming(folder) {
+        return *folder_naming.get_or_insert(folder.to_path_buf(), naming);
+    }
+
+    NAMING.get().copied().unwrap_or_default()
+}
+
+/// Record naming of an existing destination folder unless it's the default one
+/// or it's already recorded.
+pub fn persist_naming(folder: &Path) {
+    let naming = naming(folder);
+    if naming == NamingScheme::default()
+        || !folder.is_dir()
+        || folder.join(NAMING_FILE).exists()
+    {
+        return;
+    }
+
+    if let Err(error) = record_naming(folder, naming) {
+        log::error!("Unable to record naming of {folder:?}: {error}");
+    }
+}
+
+/// Record naming of backup files in a destination folder.
+pub fn record_naming(folder: &Path, naming: NamingScheme) -> IoResult<()> {
+    let path = folder.join(NAMING_FILE);
+    if dry_run::enabled() {
+        log::info!("Dry run: would record {naming:?} naming in {path:?}");
+        return Ok(());
+    }
+
+    let name = naming
+        .to_possible_value()
+        .map(|value| value.get_name().to_owned())
+        .unwrap_or_default();
+    write(path, format!("{name}\n"))
+}
+
+/// Naming recorded in a destination folder.
+fn recorded_naming(folder: &Path) -> Option<NamingScheme> {
+    let path = folder.join(NAMING_FILE);
+    let content = read_to_string(&path).ok()?;
+
+    match NamingScheme::from_str(content.trim(), true) {
+        Ok(naming) => Some(naming),
+        Err(error) => {
+            log::warn!("Ignoring naming recorded in {path:?}: {error}");
+            None
+        }
+    }
+}
@@ -2,6 +2,9 @@ use core::fmt::{Display, Formatter, Result as FmtResult};
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 
+use file_id::{FileId, get_file_id};
+
+use crate::args::{NamingScheme, SymlinkPolicy};
 use crate::time_utils;
 
 use super::StateInitializeError;
@@ -13,6 +16,12 @@ pub struct SourceInfo {
 
     /// Extension part of source file if any.
     pub extension: Option<OsString>,
+
+    /// Alias of the source used as prefix instead of its file stem.
+    pub alias: Option<String>,
+
+    /// How the source is copied when it's a symbolic link.
+    pub symlinks: SymlinkPolicy,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -35,6 +44,9 @@ pub(super) struct State {
     /// Current last modification time of the last backed up file. `0` if not yet
     /// backed up.
     last_time: u128,
+
+    /// Device and inode (or platform equivalent) of the source file if known.
+    identity: Option<FileId>,
 }
 
 impl TryFrom<&Path> for SourceInfo {
@@ -50,10 +62,95 @@ impl TryFrom<&Path> for SourceInfo {
         Ok(Self {
             prefix: prefix.to_owned(),
             extension: value.extension().map(ToOwned::to_owned),
+            alias: None,
+            symlinks: SymlinkPolicy::default(),
         })
     }
 }
 
+/// Extension of tombstone files marking a deleted source.
+pub const TOMBSTONE_EXTENSION: &str = "deleted";
+
+impl SourceInfo {
+    /// Name parts of a source, an alias replaces its file stem.
+    pub fn with_alias(
+        source: &Path,
+        alias: Option<&str>,
+    ) -> Result<Self, StateInitializeError> {
+        let mut source_info = Self::try_from(source)?;
+        if let Some(name) = alias {
+            source_info.prefix = OsString::from(name);
+            source_info.alias = Some(name.to_owned());
+        }
+
+        Ok(source_info)
+    }
+
+    /// Source path for logs followed by its alias if any.
+    pub fn describe(&self, source: &Path) -> String {
+        self.alias.as_ref().map_or_else(
+            || format!("{source:?}"),
+            |alias| format!("{source:?} ({alias})"),
+        )
+    }
+
+    /// Backup file path for given id: `<prefix>_<hex id>.<extension>`.
+    pub fn backup_path(&self, destination: &Path, file_id: u64) -> PathBuf {
+        self.versioned_path(destination, file_id, false, super::naming(destination))
+    }
+
+    /// Tombstone file path for given id: `<prefix>_<hex id>.<extension>.deleted`.
+    pub fn tombstone_path(&self, destination: &Path, file_id: u64) -> PathBuf {
+        self.versioned_path(destination, file_id, true, super::naming(destination))
+    }
+
+    /// Backup or tombstone file path for given id and naming.
+    pub fn versioned_path(
+        &self,
+        destination: &Path,
+        file_id: u64,
+        tombstone: bool,
+        naming: NamingScheme,
+    ) -> PathBuf {
+        let mut filename = self.prefix.clone();
+        filename.push("_");
+        filename.push(naming.format_id(file_id));
+
+        // Not `set_extension`: it would replace a part of dotted prefixes.
+        if let Some(extension) = &self.extension {
+            filename.push(".");
+            filename.push(extension);
+        }
+        if tombstone {
+            filename.push(".");
+            filename.push(TOMBSTONE_EXTENSION);
+        }
+
+        destination.join(filename)
+    }
+}
+
+impl NamingScheme {
+    /// Format version id for a file name.
+    pub fn format_id(self, file_id: u64) -> String {
+        match self {
+            Self::Hex => format!("{file_id:x}"),
+            Self::PaddedHex => format!("{file_id:08x}"),
+            Self::Decimal => file_id.to_string(),
+        }
+    }
+
+    /// Parse version id from a file name.
+    pub fn parse_id(self, value: &str) -> Option<u64> {
+        let radix = match self {
+            Self::Hex | Self::PaddedHex => 16,
+            Self::Decimal => 10,
+        };
+
+        u64::from_str_radix(value, radix).ok()
+    }
+}
+
 impl State {
     #[inline]
     pub const fn new(
@@ -67,13 +164,42 @@ impl State {
             source_info,
             file_id: number,
             last_time,
+            identity: None,
         }
     }
 
+    pub(super) const fn source_info(&self) -> &SourceInfo {
+        &self.source_info
+    }
+
+    pub(super) fn destination(&self) -> &Path {
+        &self.destination
+    }
+
     pub(super) const fn last_time(&self) -> u128 {
         self.last_time
     }
 
+    pub(super) const fn file_id(&self) -> u64 {
+        self.file_id
+    }
+
+    pub(super) const fn identity(&self) -> Option<FileId> {
+        self.identity
+    }
+
+    /// Read identity of the source file.
+    ///
+    /// Atomic saves replace the file, so it must be refreshed after each backup.
+    pub(super) fn refresh_identity(&mut self, source: &Path) {
+        self.identity = get_file_id(source).ok();
+    }
+
+    /// Forget identity of a removed source, a new file may reuse its inode.
+    pub(super) const fn forget_identity(&mut self) {
+        self.identity = None;
+    }
+
     /// Update state inside acquired lock using `update_fn`.
     pub(super) fn update<F>(&mut self, update_fn: F)
     where
@@ -146,6 +272,16 @@ impl StateUpdate {
         }
     }
 
+    /// Backup of an unchanged file requested explicitly.
+    pub fn forced_backup(file_id: u64, last_time: u128) -> Self {
+        assert_ne!(last_time, 0, "Last modified time must not be 0");
+        Self {
+            file_id,
+            last_time,
+            force_update: true,
+        }
+    }
+
     /// Construct reset event.
     pub fn reset(file_id: u64) -> Self {
         assert_ne!(file_id, 0, "File id must not be 0");
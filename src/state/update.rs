@@ -1,32 +1,176 @@
-use std::path::Path;
+use core::cell::Cell;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
+use file_id::get_file_id;
+
 use super::{SourceInfo, StateUpdate};
 
-/// Update state for specific path using `update_fn`.
-pub fn update_state<F>(path: &Path, update_fn: F)
+/// Update state of each destination for specific path using `update_fn`.
+///
+/// Destinations are updated in order, a failed one doesn't stop the others.
+/// Return true if any state was changed.
+pub fn update_state<F>(path: &Path, update_fn: F) -> bool
 where
     F: Fn(&Path, &SourceInfo, u64, u128) -> StateUpdate,
 {
     let current_state = super::STATE.pin();
+    let updated = Cell::new(false);
 
-    let update_result = current_state.update(path.to_path_buf(), |state| {
+    let update_result = current_state.update(path.to_path_buf(), |states| {
         let current_time = Instant::now();
         #[allow(clippy::shadow_reuse, reason = "Cloned state, original is not needed")]
-        let mut state = state.clone();
-        let last_time = state.last_time();
-        state.update(&update_fn);
+        let mut states = states.clone();
+        let several = states.len() > 1;
+        updated.set(false);
 
-        if state.last_time() != last_time {
-            let elapsed = current_time.elapsed().as_nanos();
+        for state in &mut states {
+            let last_time = state.last_time();
+            let file_id = state.file_id();
+            state.update(&update_fn);
+            if state.last_time() == last_time && state.file_id() == file_id {
+                continue;
+            }
 
+            updated.set(true);
+            // Reset state belongs to a removed file.
+            if state.last_time() == 0 {
+                state.forget_identity();
+            } else {
+                state.refresh_identity(path);
+            }
+            let source = state.source_info().describe(path);
+            if several {
+                log::info!(
+                    "Current state for {source} in {:?}: {state}",
+                    state.destination()
+                );
+            } else {
+                log::info!("Current state for {source}: {state}");
+            }
+        }
+
+        if updated.get() {
+            let elapsed = current_time.elapsed().as_nanos();
             log::debug!("Process time {elapsed} ns for {path:?}");
-            log::info!("Current state for {path:?}: {state}");
         }
-        state
+        states
     });
 
     if update_result.is_none() {
         log::error!("Trying to update unregistered path: \"{path:?}\"");
     }
+
+    updated.get()
+}
+
+/// Move state of a file renamed from `from` to `to`.
+///
+/// The state is moved only if the file at `to` still has the identity recorded by
+/// the last backup of `from`. Return true if the state was moved.
+pub fn follow_file(from: &Path, to: &Path) -> bool {
+    let Ok(identity) = get_file_id(to) else {
+        return false;
+    };
+
+    let current_state = super::STATE.pin();
+    if current_state.contains_key(to) {
+        return false;
+    }
+
+    let same_file = current_state.get(from).is_some_and(|states| {
+        states
+            .iter()
+            .any(|state| state.identity() == Some(identity))
+    });
+    if !same_file {
+        return false;
+    }
+
+    if !move_state(from, to) {
+        return false;
+    }
+
+    log::info!("Following renamed file {from:?} -> {to:?}");
+    true
+}
+
+/// Move state of a registered path to another path unless it's registered.
+///
+/// Return true if the state was moved.
+pub(super) fn move_state(from: &Path, to: &Path) -> bool {
+    let current_state = super::STATE.pin();
+    if current_state.contains_key(to) {
+        return false;
+    }
+
+    let Some(state) = current_state.remove(from).cloned() else {
+        return false;
+    };
+
+    if current_state.try_insert(to.to_path_buf(), state).is_err() {
+        log::warn!("Unable to follow {from:?}: {to:?} is already registered");
+        return false;
+    }
+
+    true
+}
+
+/// Tombstone paths for the current id of a registered source in each destination.
+pub fn tombstone_paths(path: &Path) -> Vec<PathBuf> {
+    super::STATE
+        .pin()
+        .get(path)
+        .map(|states| {
+            states
+                .iter()
+                .map(|state| {
+                    state
+                        .source_info()
+                        .tombstone_path(state.destination(), state.file_id())
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// All registered source paths.
+pub fn registered_paths() -> Vec<PathBuf> {
+    super::STATE.pin().keys().cloned().collect()
+}
+
+/// Registered source paths with their current backup id and last backup time.
+///
+/// The most recent state is taken if destinations diverge.
+pub fn source_states() -> Vec<(PathBuf, u64, u128)> {
+    super::STATE
+        .pin()
+        .iter()
+        .filter_map(|(path, states)| {
+            states
+                .iter()
+                .max_by_key(|state| (state.last_time(), state.file_id()))
+                .map(|state| (path.clone(), state.file_id(), state.last_time()))
+        })
+        .collect()
+}
+
+/// Forget registered paths matching the predicate, return removed paths.
+pub fn unregister<F>(predicate: F) -> Vec<PathBuf>
+where
+    F: Fn(&Path) -> bool,
+{
+    let current_state = super::STATE.pin();
+    let paths = current_state
+        .keys()
+        .filter(|path| predicate(path))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for path in &paths {
+        current_state.remove(path);
+        log::info!("Unregistered {path:?}");
+    }
+
+    paths
 }
@@ -1,12 +1,23 @@
+use core::time::Duration;
+use std::fs::Metadata;
 use std::io::Error;
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone as _};
 
 /// Filesystem time in nanoseconds.
+///
+/// Symbolic links are not followed: a link backed up as a link with
+/// `--symlinks copy-link` changes when the link itself changes. Followed links
+/// are timed by their targets. Times of regular files are the same either way.
 pub fn fs_time(file: &Path) -> Result<u128, Error> {
-    Ok(as_nanos(file.metadata()?.modified()?))
+    modified_time(&file.symlink_metadata()?)
+}
+
+/// Modification time from metadata in nanoseconds.
+pub fn modified_time(metadata: &Metadata) -> Result<u128, Error> {
+    Ok(as_nanos(metadata.modified()?))
 }
 
 /// Utility function to convert time into nanoseconds.
@@ -15,6 +26,12 @@ fn as_nanos(time: SystemTime) -> u128 {
         .map_or(0, |dur| dur.as_nanos())
 }
 
+/// Convert time in nanoseconds into system time.
+pub fn system_time(nanos: u128) -> SystemTime {
+    let clamped = u64::try_from(nanos).unwrap_or(u64::MAX);
+    UNIX_EPOCH + Duration::from_nanos(clamped)
+}
+
 /// Format given time to human-readable string in system local timezone.
 pub fn format_time(time: u128) -> String {
     if time > i64::MAX as u128 {
@@ -24,3 +41,33 @@ pub fn format_time(time: u128) -> String {
         date.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
     }
 }
+
+/// Parse user provided time into nanoseconds.
+///
+/// Accepts RFC 3339 and `YYYY-MM-DD[ HH:MM[:SS]]` in system local timezone.
+pub fn parse_time(input: &str) -> Result<u128, String> {
+    let value = input.trim();
+
+    let date = if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        date.with_timezone(&Local)
+    } else {
+        let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            .or_else(|| {
+                NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .ok()?
+                    .and_hms_opt(0, 0, 0)
+            })
+            .ok_or_else(|| format!("unrecognized time '{value}'"))?;
+
+        Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .ok_or_else(|| format!("time '{value}' doesn't exist in local timezone"))?
+    };
+
+    date.timestamp_nanos_opt()
+        .and_then(|nanos| u128::try_from(nanos).ok())
+        .ok_or_else(|| format!("time '{value}' is out of range"))
+}
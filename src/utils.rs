@@ -1,4 +1,5 @@
-use std::path::Path;
+use std::env;
+use std::path::{Path, PathBuf};
 
 pub trait ParentPath {
     fn parent_path(&self) -> &Path;
@@ -11,3 +12,18 @@ impl<T: AsRef<Path>> ParentPath for T {
         self.as_ref().parent().unwrap_or(self.as_ref())
     }
 }
+
+/// Home folder of the user.
+pub fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+/// XDG base folder from its variable or its default under home.
+pub fn xdg_folder(variable: &str, home: &Path, default: &str) -> PathBuf {
+    env::var_os(variable)
+        .filter(|folder| !folder.is_empty())
+        .map_or_else(|| home.join(default), PathBuf::from)
+}